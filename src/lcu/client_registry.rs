@@ -0,0 +1,192 @@
+use crate::lcu::utils::LolClientConnectInfo;
+use std::path::Path;
+
+/// 发现的连接信息的处理方式
+pub enum Assignment<C> {
+    /// 新出现的客户端，需要创建并通过 insert 登记
+    New(LolClientConnectInfo),
+    /// 已登记的客户端重启后端口发生了变化，需要把新的连接信息交给它
+    Restarted(C, LolClientConnectInfo),
+}
+
+/// 按端口登记正在运行的客户端，同一安装目录下可以同时运行多个客户端（例如不同 Wine 前缀中的客户端）
+///
+/// 客户端重启后端口会变化，此时把新的连接信息交给同一安装目录下端口已经消失且未连接的客户端，
+/// 而不是再创建一个
+pub struct ClientRegistry<C> {
+    clients: Vec<(C, LolClientConnectInfo)>,
+}
+
+impl<C> Default for ClientRegistry<C> {
+    fn default() -> Self {
+        ClientRegistry { clients: Vec::new() }
+    }
+}
+
+impl<C: Clone> ClientRegistry<C> {
+    /// 根据本次发现的连接信息决定需要创建或更新的客户端，已登记的端口忽略，
+    /// is_connected 用于排除仍然连接着旧端口的客户端
+    pub fn assign(&mut self, infos: Vec<LolClientConnectInfo>, is_connected: impl Fn(&C) -> bool) -> Vec<Assignment<C>> {
+        let ports: Vec<i32> = infos.iter().map(|info| info.port).collect();
+        let mut assignments = Vec::new();
+        for info in infos {
+            if self.clients.iter().any(|(_, known)| known.port == info.port) {
+                continue;
+            }
+            let restarted = self.clients.iter_mut().find(|(client, known)| {
+                !ports.contains(&known.port) && same_install(known, &info) && !is_connected(client)
+            });
+            match restarted {
+                Some((client, known)) => {
+                    *known = info.clone();
+                    assignments.push(Assignment::Restarted(client.clone(), info));
+                }
+                None => assignments.push(Assignment::New(info)),
+            }
+        }
+        assignments
+    }
+
+    /// 登记新创建的客户端
+    pub fn insert(&mut self, client: C, info: LolClientConnectInfo) {
+        self.clients.push((client, info));
+    }
+
+    /// 移除满足条件的客户端
+    pub fn remove_where(&mut self, predicate: impl Fn(&C) -> bool) {
+        self.clients.retain(|(client, _)| !predicate(client));
+    }
+
+    /// 已登记的客户端数量
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// 是否没有登记任何客户端
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+}
+
+/// 两个连接信息是否来自同一安装目录，没有安装目录时无法判断，视为不同
+fn same_install(a: &LolClientConnectInfo, b: &LolClientConnectInfo) -> bool {
+    match (&a.install_directory, &b.install_directory) {
+        (Some(a), Some(b)) => normalize_install_dir(a) == normalize_install_dir(b),
+        _ => false,
+    }
+}
+
+/// 统一安装目录的写法，使 lockfile 所在的真实路径与启动命令中的路径可以比较：
+/// 统一使用 `/` 分隔、忽略大小写和末尾的分隔符，Wine 前缀中 `drive_c` 之后的部分按 `c:/` 处理
+fn normalize_install_dir(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let path = match path.find("/drive_c/") {
+        Some(index) => format!("c:/{}", &path[index + "/drive_c/".len()..]),
+        None => path,
+    };
+    path.trim_end_matches('/').to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const INSTALL_DIR: &str = "C:/Riot Games/League of Legends";
+
+    fn info(port: i32, install_directory: Option<&str>) -> LolClientConnectInfo {
+        LolClientConnectInfo {
+            port,
+            token: format!("token{}", port),
+            install_directory: install_directory.map(PathBuf::from),
+            ..Default::default()
+        }
+    }
+
+    /// 登记所有新的客户端，客户端用编号表示
+    fn register(registry: &mut ClientRegistry<u32>, infos: Vec<LolClientConnectInfo>, next: &mut u32) {
+        for assignment in registry.assign(infos, |_| false) {
+            if let Assignment::New(info) = assignment {
+                registry.insert(*next, info);
+                *next += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn clients_sharing_install_dir_are_kept_apart() {
+        let mut registry = ClientRegistry::default();
+        let mut next = 0;
+        let infos = vec![info(1000, Some(INSTALL_DIR)), info(2000, Some(INSTALL_DIR))];
+        register(&mut registry, infos.clone(), &mut next);
+        assert_eq!(registry.len(), 2);
+        // 再次发现同样的客户端时不产生任何操作
+        assert!(registry.assign(infos, |_| false).is_empty());
+    }
+
+    #[test]
+    fn restarted_client_keeps_its_lcu_client() {
+        let mut registry = ClientRegistry::default();
+        let mut next = 0;
+        register(&mut registry, vec![info(1000, Some(INSTALL_DIR)), info(2000, Some(INSTALL_DIR))], &mut next);
+        // 端口 1000 的客户端重启为 3000
+        let assignments = registry.assign(vec![info(2000, Some(INSTALL_DIR)), info(3000, Some(INSTALL_DIR))], |_| false);
+        match assignments.as_slice() {
+            [Assignment::Restarted(client, info)] => {
+                assert_eq!(*client, 0);
+                assert_eq!(info.port, 3000);
+            }
+            _ => panic!("expected one restarted client"),
+        }
+        assert_eq!(registry.len(), 2);
+    }
+
+    #[test]
+    fn connected_client_is_not_reassigned() {
+        let mut registry = ClientRegistry::default();
+        let mut next = 0;
+        register(&mut registry, vec![info(1000, Some(INSTALL_DIR))], &mut next);
+        let assignments = registry.assign(vec![info(2000, Some(INSTALL_DIR))], |_| true);
+        assert!(matches!(assignments.as_slice(), [Assignment::New(info)] if info.port == 2000));
+    }
+
+    #[test]
+    fn client_without_install_dir_is_not_reassigned() {
+        let mut registry = ClientRegistry::default();
+        let mut next = 0;
+        register(&mut registry, vec![info(1000, None)], &mut next);
+        let assignments = registry.assign(vec![info(2000, None)], |_| false);
+        assert!(matches!(assignments.as_slice(), [Assignment::New(_)]));
+    }
+
+    #[test]
+    fn lockfile_and_command_line_paths_match() {
+        let command_line = Path::new(INSTALL_DIR);
+        assert_eq!(normalize_install_dir(Path::new(r"C:\Riot Games\League of Legends\")), normalize_install_dir(command_line));
+        assert_eq!(
+            normalize_install_dir(Path::new("/home/user/Games/league-of-legends/drive_c/Riot Games/League of Legends")),
+            normalize_install_dir(command_line)
+        );
+        assert_ne!(normalize_install_dir(Path::new("D:/Riot Games/League of Legends")), normalize_install_dir(command_line));
+    }
+
+    #[test]
+    fn lockfile_info_restarts_client_found_by_command_line() {
+        let mut registry = ClientRegistry::default();
+        let mut next = 0;
+        register(&mut registry, vec![info(1000, Some(INSTALL_DIR))], &mut next);
+        let lockfile = info(2000, Some("/home/user/Games/league-of-legends/drive_c/Riot Games/League of Legends"));
+        let assignments = registry.assign(vec![lockfile], |_| false);
+        assert!(matches!(assignments.as_slice(), [Assignment::Restarted(0, info)] if info.port == 2000));
+    }
+
+    #[test]
+    fn removed_client_is_replaced_by_new_one() {
+        let mut registry = ClientRegistry::default();
+        let mut next = 0;
+        register(&mut registry, vec![info(1000, Some(INSTALL_DIR))], &mut next);
+        registry.remove_where(|client| *client == 0);
+        let assignments = registry.assign(vec![info(1000, Some(INSTALL_DIR))], |_| false);
+        assert!(matches!(assignments.as_slice(), [Assignment::New(_)]));
+    }
+}
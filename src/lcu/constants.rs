@@ -1,30 +1,55 @@
+use serde_derive::{Deserialize, Serialize};
+
 pub trait Value<T> {
     fn value(&self) -> T;
 
     fn from_value(val: T) -> Self;
 }
 
+/// LCU websocket 使用的 WAMP 1.0 消息类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operator {
+    Welcome,
+    Prefix,
+    Call,
+    CallResult,
+    CallError,
     Sub,
     DisSub,
+    Publish,
     Event,
+    /// 协议之外的消息类型
+    Unknown(i32),
 }
 
 impl Value<i32> for Operator {
     fn value(&self) -> i32 {
         match self {
+            Operator::Welcome => 0,
+            Operator::Prefix => 1,
+            Operator::Call => 2,
+            Operator::CallResult => 3,
+            Operator::CallError => 4,
             Operator::Sub => 5,
             Operator::DisSub => 6,
+            Operator::Publish => 7,
             Operator::Event => 8,
+            Operator::Unknown(val) => *val,
         }
     }
 
     fn from_value(val: i32) -> Self {
         match val {
+            0 => Operator::Welcome,
+            1 => Operator::Prefix,
+            2 => Operator::Call,
+            3 => Operator::CallResult,
+            4 => Operator::CallError,
             5 => Operator::Sub,
             6 => Operator::DisSub,
+            7 => Operator::Publish,
             8 => Operator::Event,
-            _ => unreachable!(),
+            _ => Operator::Unknown(val),
         }
     }
 }
@@ -33,6 +58,14 @@ pub enum Event {
     OnJsonApiEvent,
 }
 
+impl Event {
+    /// 只订阅指定接口的事件主题，例如 `/lol-gameflow/v1/gameflow-phase`
+    /// 对应 `OnJsonApiEvent_lol-gameflow_v1_gameflow-phase`
+    pub fn topic(&self, uri: &str) -> String {
+        format!("{}{}", self.value(), uri.replace('/', "_"))
+    }
+}
+
 impl Value<&str> for Event {
     fn value(&self) -> &'static str {
         match self {
@@ -48,64 +81,87 @@ impl Value<&str> for Event {
     }
 }
 
-#[derive(Eq, Hash, PartialEq)]
+/// 游戏状态，对应 /lol-gameflow/v1/gameflow-phase，序列化为客户端使用的名称
+#[derive(Debug, Default, Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum GameState {
+    #[default]
     None,
     Lobby,
+    #[serde(rename = "Matchmaking")]
     MatchMaking,
+    CheckedIntoTournament,
     ReadyCheck,
     ChampSelect,
     GameStart,
+    FailedToLaunch,
     InProgress,
     PreEndOfGame,
     WaitingForStats,
     EndOfGame,
+    TerminatedInError,
     Reconnect,
     WatchInProgress,
+    /// 未知的游戏状态，保留客户端发送的名称
+    #[serde(untagged)]
+    Unknown(String),
 }
-impl Value<&str> for GameState {
-    fn value(&self) -> &'static str {
-        match self {
+impl Value<String> for GameState {
+    fn value(&self) -> String {
+        let value = match self {
             GameState::None => "None",
             GameState::Lobby => "Lobby",
             GameState::MatchMaking => "Matchmaking",
+            GameState::CheckedIntoTournament => "CheckedIntoTournament",
             GameState::ReadyCheck => "ReadyCheck",
             GameState::ChampSelect => "ChampSelect",
             GameState::GameStart => "GameStart",
+            GameState::FailedToLaunch => "FailedToLaunch",
             GameState::InProgress => "InProgress",
             GameState::PreEndOfGame => "PreEndOfGame",
             GameState::WaitingForStats => "WaitingForStats",
             GameState::EndOfGame => "EndOfGame",
+            GameState::TerminatedInError => "TerminatedInError",
             GameState::Reconnect => "Reconnect",
             GameState::WatchInProgress => "WatchInProgress",
-        }
+            GameState::Unknown(val) => val,
+        };
+        value.to_string()
     }
 
-    fn from_value(val: &str) -> Self {
-        match val {
+    fn from_value(val: String) -> Self {
+        match val.as_str() {
             "None" => GameState::None,
             "Lobby" => GameState::Lobby,
             "Matchmaking" => GameState::MatchMaking,
+            "CheckedIntoTournament" => GameState::CheckedIntoTournament,
             "ReadyCheck" => GameState::ReadyCheck,
             "ChampSelect" => GameState::ChampSelect,
             "GameStart" => GameState::GameStart,
+            "FailedToLaunch" => GameState::FailedToLaunch,
             "InProgress" => GameState::InProgress,
             "PreEndOfGame" => GameState::PreEndOfGame,
             "WaitingForStats" => GameState::WaitingForStats,
             "EndOfGame" => GameState::EndOfGame,
+            "TerminatedInError" => GameState::TerminatedInError,
             "Reconnect" => GameState::Reconnect,
             "WatchInProgress" => GameState::WatchInProgress,
-            _ => {
-                println!("unreachable! GameState {}",val);
-                GameState::None
-            },
+            _ => GameState::Unknown(val),
         }
     }
 }
 
+/// 按名称获取游戏状态，用于为未知的游戏状态注册回调，例如 `GameState::from("NewPhase")`
+impl From<&str> for GameState {
+    fn from(val: &str) -> Self {
+        GameState::from_value(val.to_string())
+    }
+}
+
 pub mod lcu_api {
     // 游戏状态
     pub const GAMEFLOW_PHASE: &str = "/lol-gameflow/v1/gameflow-phase";
+    // 对局流程会话
+    pub const GAMEFLOW_SESSION: &str = "/lol-gameflow/v1/session";
     // 接受对局
     pub const GAME_ACCEPT: &str = "/lol-matchmaking/v1/ready-check/accept";
     // 再来一局
@@ -114,4 +170,62 @@ pub mod lcu_api {
     pub const GAME_SEARCH: &str = "/lol-lobby/v2/lobby/matchmaking/search";
     // 给队友点赞
     pub const HONOR_PLAYER: &str = "/lol-honor-v2/v1/honor-player";
+    // 当前召唤师
+    pub const CURRENT_SUMMONER: &str = "/lol-summoner/v1/current-summoner";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN: [GameState; 15] = [
+        GameState::None,
+        GameState::Lobby,
+        GameState::MatchMaking,
+        GameState::CheckedIntoTournament,
+        GameState::ReadyCheck,
+        GameState::ChampSelect,
+        GameState::GameStart,
+        GameState::FailedToLaunch,
+        GameState::InProgress,
+        GameState::PreEndOfGame,
+        GameState::WaitingForStats,
+        GameState::EndOfGame,
+        GameState::TerminatedInError,
+        GameState::Reconnect,
+        GameState::WatchInProgress,
+    ];
+
+    #[test]
+    fn matchmaking_uses_client_name() {
+        let state: GameState = serde_json::from_str(r#""Matchmaking""#).unwrap();
+        assert_eq!(state, GameState::MatchMaking);
+        assert_eq!(serde_json::to_string(&GameState::MatchMaking).unwrap(), r#""Matchmaking""#);
+    }
+
+    #[test]
+    fn known_states_serialize_as_value() {
+        for state in KNOWN {
+            let json = serde_json::to_string(&state).unwrap();
+            assert_eq!(json, format!("\"{}\"", state.value()));
+            assert_eq!(serde_json::from_str::<GameState>(&json).unwrap(), state);
+            assert_eq!(GameState::from_value(state.value()), state);
+        }
+    }
+
+    #[test]
+    fn unknown_state_keeps_its_name() {
+        let state: GameState = serde_json::from_str(r#""NewPhase""#).unwrap();
+        assert_eq!(state, GameState::Unknown("NewPhase".to_string()));
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(json, r#""NewPhase""#);
+        assert_eq!(serde_json::from_str::<GameState>(&json).unwrap().value(), "NewPhase");
+    }
+
+    #[test]
+    fn from_str_returns_known_variant() {
+        assert_eq!(GameState::from("ReadyCheck"), GameState::ReadyCheck);
+        assert_eq!(GameState::from("Matchmaking"), GameState::MatchMaking);
+        assert_eq!(GameState::from("NewPhase"), GameState::Unknown("NewPhase".to_string()));
+    }
 }
@@ -0,0 +1,145 @@
+use serde_derive::Deserialize;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// LCU 接口调用失败的原因
+#[derive(Debug)]
+pub enum LcuError {
+    /// 请求发送失败，例如客户端未启动、连接被拒绝或超时
+    Request(reqwest::Error),
+    /// LCU 返回了非 2xx 的状态码
+    Api {
+        status: u16,
+        /// LCU 错误码，例如 RPC_ERROR
+        error_code: Option<String>,
+        message: Option<String>,
+    },
+    /// 请求体序列化或响应体反序列化失败
+    Json(serde_json::Error),
+    /// websocket 上的 WAMP 调用返回了 CallError
+    Call {
+        /// 错误 URI
+        error_uri: String,
+        description: String,
+        details: Option<serde_json::Value>,
+    },
+    /// websocket 连接已关闭，调用没有返回结果
+    Disconnected,
+}
+
+/// LCU 返回的错误响应体
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LcuErrorBody {
+    error_code: Option<String>,
+    http_status: Option<u16>,
+    message: Option<String>,
+}
+
+impl LcuError {
+    /// 根据状态码和响应体构造错误，响应体不是 LCU 的错误格式时只保留状态码和原始内容
+    pub(in crate::lcu) fn from_response(status: u16, body: &str) -> Self {
+        match serde_json::from_str::<LcuErrorBody>(body) {
+            // 字段都是可选的，任意 JSON 对象都能解析成功，三个字段都不存在时按原始内容处理
+            Ok(error) if error.error_code.is_some() || error.http_status.is_some() || error.message.is_some() => LcuError::Api {
+                status: error.http_status.unwrap_or(status),
+                error_code: error.error_code,
+                message: error.message,
+            },
+            _ => LcuError::Api {
+                status,
+                error_code: None,
+                message: Some(body.to_string()).filter(|body| !body.is_empty()),
+            },
+        }
+    }
+
+    /// LCU 返回的状态码，请求未发送成功时为 None
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            LcuError::Request(e) => e.status().map(|status| status.as_u16()),
+            LcuError::Api { status, .. } => Some(*status),
+            LcuError::Json(_) | LcuError::Call { .. } | LcuError::Disconnected => None,
+        }
+    }
+}
+
+impl Display for LcuError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LcuError::Request(e) => write!(f, "请求失败: {}", e),
+            LcuError::Api { status, error_code, message } => {
+                write!(f, "LCU 返回错误 {}", status)?;
+                if let Some(error_code) = error_code {
+                    write!(f, " {}", error_code)?;
+                }
+                if let Some(message) = message {
+                    write!(f, ": {}", message)?;
+                }
+                Ok(())
+            }
+            LcuError::Json(e) => write!(f, "JSON 解析失败: {}", e),
+            LcuError::Call { error_uri, description, .. } => write!(f, "调用失败 {}: {}", error_uri, description),
+            LcuError::Disconnected => write!(f, "websocket 连接已关闭"),
+        }
+    }
+}
+
+impl Error for LcuError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LcuError::Request(e) => Some(e),
+            LcuError::Json(e) => Some(e),
+            LcuError::Api { .. } | LcuError::Call { .. } | LcuError::Disconnected => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for LcuError {
+    fn from(e: reqwest::Error) -> Self {
+        LcuError::Request(e)
+    }
+}
+
+impl From<serde_json::Error> for LcuError {
+    fn from(e: serde_json::Error) -> Self {
+        LcuError::Json(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api(error: LcuError) -> (u16, Option<String>, Option<String>) {
+        match error {
+            LcuError::Api { status, error_code, message } => (status, error_code, message),
+            other => panic!("expected api error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_response_reads_lcu_error_body() {
+        let body = r#"{"errorCode":"RPC_ERROR","httpStatus":404,"implementationDetails":{},"message":"No active delegate"}"#;
+        assert_eq!(
+            api(LcuError::from_response(500, body)),
+            (404, Some("RPC_ERROR".to_string()), Some("No active delegate".to_string()))
+        );
+    }
+
+    #[test]
+    fn from_response_keeps_plain_text_body() {
+        assert_eq!(api(LcuError::from_response(502, "Bad Gateway")), (502, None, Some("Bad Gateway".to_string())));
+    }
+
+    #[test]
+    fn from_response_keeps_unrelated_json_body() {
+        let body = r#"{"foo":"bar"}"#;
+        assert_eq!(api(LcuError::from_response(400, body)), (400, None, Some(body.to_string())));
+    }
+
+    #[test]
+    fn from_response_ignores_empty_body() {
+        assert_eq!(api(LcuError::from_response(404, "")), (404, None, None));
+    }
+}
@@ -0,0 +1,61 @@
+use crate::lcu::handler::BoxFuture;
+use crate::lcu::lcu_client::LcuContext;
+use crate::lcu::lcu_listener::LcuData;
+use crate::lcu::router::PathParams;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+
+/// LCU 事件类型
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LcuEventType {
+    Create,
+    #[default]
+    Update,
+    Delete,
+    /// 协议之外的事件类型
+    #[serde(other)]
+    Unknown,
+}
+
+/// 数据已反序列化的事件
+#[derive(Debug, Clone, PartialEq)]
+pub struct LcuEvent<T> {
+    pub uri: String,
+    pub event_type: LcuEventType,
+    /// 从 URI 中提取的路径参数
+    pub params: PathParams,
+    /// Delete 事件的数据为 null，需要接收 Delete 事件时 T 应为 Option
+    pub data: T,
+}
+
+impl<T: DeserializeOwned> LcuEvent<T> {
+    /// 将原始事件的数据反序列化为 T
+    pub fn from_data(lcu_data: &LcuData, params: PathParams) -> Result<Self, serde_json::Error> {
+        Ok(LcuEvent {
+            uri: lcu_data.uri.clone(),
+            event_type: lcu_data.event_type,
+            params,
+            data: serde_json::from_value(lcu_data.data.clone())?,
+        })
+    }
+}
+
+/// 类型擦除后的事件回调，反序列化失败时返回错误
+pub(in crate::lcu) type RawEventCallback = Arc<
+    dyn Fn(&LcuData, PathParams, LcuContext) -> Result<BoxFuture, serde_json::Error> + Send + Sync,
+>;
+
+/// 将指定类型的回调包装为类型擦除的回调
+pub(in crate::lcu) fn erase_event_callback<T, F, Fut>(callback: F) -> RawEventCallback
+where
+    T: DeserializeOwned + Send + 'static,
+    F: Fn(LcuEvent<T>, LcuContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output=()> + Send + 'static,
+{
+    Arc::new(move |lcu_data, params, ctx| {
+        let event = LcuEvent::from_data(lcu_data, params)?;
+        Ok(Box::pin(callback(event, ctx)) as BoxFuture)
+    })
+}
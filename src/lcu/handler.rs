@@ -0,0 +1,223 @@
+use crate::lcu::constants::{GameState, Value};
+use crate::lcu::lcu_client::{ConnectionEvent, LcuContext};
+use crate::lcu::utils::get_now_str;
+use futures::future::join_all;
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// 回调返回的 Future
+pub type BoxFuture = Pin<Box<dyn Future<Output=()> + Send>>;
+
+/// 游戏状态回调，返回 Future 的函数和闭包都实现了该 trait，闭包可以捕获配置
+///
+/// 例如 `client.add_game_flow_action(GameState::ReadyCheck, move |ctx| async move { ... })`
+pub trait Handler: Send + Sync {
+    fn call(&self, ctx: LcuContext) -> BoxFuture;
+}
+
+impl<F, Fut> Handler for F
+where
+    F: Fn(LcuContext) -> Fut + Send + Sync,
+    Fut: Future<Output=()> + Send + 'static,
+{
+    fn call(&self, ctx: LcuContext) -> BoxFuture {
+        Box::pin(self(ctx))
+    }
+}
+
+/// 类型擦除后的连接事件回调
+pub(in crate::lcu) type ConnectionCallback = Arc<dyn Fn(ConnectionEvent) -> BoxFuture + Send + Sync>;
+
+/// 注册回调时分配的 ID，用于移除、启用和禁用回调
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HandlerId(pub(in crate::lcu) u64);
+
+impl Display for HandlerId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// 回调默认的超时时间
+pub const DEFAULT_HANDLER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 注册回调的选项
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandlerOptions {
+    /// 回调名称，为 None 时使用回调的类型名
+    pub name: Option<String>,
+    /// 顺序执行时优先级高的先执行，相同优先级按注册顺序执行
+    pub priority: i32,
+    /// 超时后放弃执行，为 None 时不限制
+    pub timeout: Option<Duration>,
+}
+
+impl Default for HandlerOptions {
+    fn default() -> Self {
+        HandlerOptions {
+            name: None,
+            priority: 0,
+            timeout: Some(DEFAULT_HANDLER_TIMEOUT),
+        }
+    }
+}
+
+impl HandlerOptions {
+    /// 指定名称，其余使用默认值
+    pub fn named(name: &str) -> Self {
+        HandlerOptions {
+            name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// 同一游戏状态的多个回调的执行方式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// 按优先级依次执行，前一个结束后才执行下一个
+    #[default]
+    Sequential,
+    /// 同时执行
+    Parallel,
+}
+
+/// 已注册回调的信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandlerInfo {
+    pub id: HandlerId,
+    pub name: String,
+    /// 触发回调的游戏状态或 URI 模式
+    pub trigger: String,
+    pub enabled: bool,
+    pub priority: i32,
+    pub timeout: Option<Duration>,
+}
+
+/// 注册的回调及其 ID、选项和启用状态
+pub(in crate::lcu) struct Registration<H> {
+    pub id: HandlerId,
+    pub name: String,
+    pub enabled: bool,
+    pub priority: i32,
+    pub timeout: Option<Duration>,
+    pub handler: H,
+}
+
+impl<H> Registration<H> {
+    /// 选项中没有名称时使用 default_name
+    pub fn new(id: HandlerId, options: HandlerOptions, default_name: &str, handler: H) -> Self {
+        Registration {
+            id,
+            name: options.name.unwrap_or_else(|| default_name.to_string()),
+            enabled: true,
+            priority: options.priority,
+            timeout: options.timeout,
+            handler,
+        }
+    }
+
+    pub fn info(&self, trigger: String) -> HandlerInfo {
+        HandlerInfo {
+            id: self.id,
+            name: self.name.clone(),
+            trigger,
+            enabled: self.enabled,
+            priority: self.priority,
+            timeout: self.timeout,
+        }
+    }
+
+    /// 创建一次执行，future 在执行时才会被轮询
+    pub fn job(&self, future: BoxFuture) -> Job {
+        Job {
+            name: self.name.clone(),
+            priority: self.priority,
+            timeout: self.timeout,
+            future,
+        }
+    }
+}
+
+/// 一次待执行的回调
+pub(in crate::lcu) struct Job {
+    name: String,
+    priority: i32,
+    timeout: Option<Duration>,
+    future: BoxFuture,
+}
+
+/// 在独立的任务中执行回调，回调 panic 或超时只打印带客户端标签前缀的日志，不影响事件循环
+pub(in crate::lcu) async fn run_jobs(mut jobs: Vec<Job>, mode: ExecutionMode, label: String) {
+    match mode {
+        ExecutionMode::Sequential => {
+            // 稳定排序，相同优先级保持注册顺序
+            jobs.sort_by_key(|job| std::cmp::Reverse(job.priority));
+            for job in jobs {
+                run_supervised(job, &label).await;
+            }
+        }
+        ExecutionMode::Parallel => {
+            join_all(jobs.into_iter().map(|job| run_supervised(job, &label))).await;
+        }
+    }
+}
+
+async fn run_supervised(job: Job, label: &str) {
+    let task = tokio::spawn(job.future);
+    let abort_handle = task.abort_handle();
+    let result = match job.timeout {
+        Some(duration) => match timeout(duration, task).await {
+            Ok(result) => result,
+            Err(_) => {
+                abort_handle.abort();
+                println!("{} {}回调 {} 执行超过 {:?}，已放弃", get_now_str(), label, job.name, duration);
+                return;
+            }
+        },
+        None => task.await,
+    };
+    if let Err(e) = result {
+        if e.is_panic() {
+            println!("{} {}回调 {} panic: {}", get_now_str(), label, job.name, panic_message(e.into_panic()));
+        } else {
+            println!("{} {}回调 {} 被取消", get_now_str(), label, job.name);
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "未知错误".to_string()
+    }
+}
+
+/// 触发游戏状态回调的时机
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GameFlowTrigger {
+    /// 收到指定游戏状态
+    Enter(GameState),
+    /// 离开指定游戏状态
+    Exit(GameState),
+    /// 从一个游戏状态变为另一个，例如 `ChampSelect -> Lobby` 表示有人秒退
+    Transition(GameState, GameState),
+}
+
+impl Display for GameFlowTrigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameFlowTrigger::Enter(state) => write!(f, "{}", state.value()),
+            GameFlowTrigger::Exit(state) => write!(f, "exit {}", state.value()),
+            GameFlowTrigger::Transition(from, to) => write!(f, "{} -> {}", from.value(), to.value()),
+        }
+    }
+}
@@ -1,89 +1,514 @@
-use super::lcu_listener::{LcuData, LcuWebsocket};
-use crate::lcu::constants::{lcu_api, GameState, Value};
-use crate::lcu::utils::{gen_lcu_auth, get_lol_client_connect_info, get_now_str};
-use reqwest::{header, Client};
-use std::collections::HashMap;
+use super::event::{erase_event_callback, LcuEvent, LcuEventType, RawEventCallback};
+use super::handler::{run_jobs, BoxFuture, ConnectionCallback, ExecutionMode, GameFlowTrigger, Handler, HandlerId, HandlerInfo, HandlerOptions, Registration};
+use super::lcu_http_client::LcuHttpClient;
+use super::lcu_listener::{CloseReason, LcuData, LcuWebsocket};
+use super::router::{EventRouter, UriPattern};
+use crate::lcu::connect_source::ConnectSource;
+use crate::lcu::constants::{lcu_api, Event, GameState, Value};
+use crate::lcu::error::LcuError;
+use crate::lcu::models::GameflowSession;
+use crate::lcu::tls::TlsMode;
+use crate::lcu::utils::{get_now_str, label_prefix, LolClientConnectInfo};
+use futures::{stream, Stream};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::future::Future;
-use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::{broadcast, Notify, RwLock};
+use tokio::sync::{broadcast, mpsc, watch, Notify, RwLock};
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 
-pub type Callback = fn() -> Pin<Box<dyn Future<Output=()> + Send>>;
+pub type Callback = Arc<dyn Handler>;
 
 struct CallbackRes {}
 
+/// 回调的上下文，携带触发回调的事件、游戏状态以及客户端对应的HTTP客户端
+#[derive(Clone)]
+pub struct LcuContext {
+    pub http_client: Arc<LcuHttpClient>,
+    /// 触发回调的事件
+    pub event: LcuData,
+    /// 事件之前的游戏状态，非游戏状态事件时与 game_state 相同
+    pub previous_state: Option<GameState>,
+    /// 当前的游戏状态，尚未收到游戏状态事件时为 None
+    pub game_state: Option<GameState>,
+    /// 回调应在取消后放弃执行：游戏状态回调在游戏状态离开触发回调的状态或连接断开时被取消，
+    /// 事件回调只在连接断开时被取消
+    pub cancel: CancellationToken,
+    /// 日志中区分多个客户端的标签，与 HTTP 客户端的标签相同
+    pub label: Option<String>,
+}
+
+impl LcuContext {
+    pub fn new(
+        http_client: Arc<LcuHttpClient>,
+        event: LcuData,
+        previous_state: Option<GameState>,
+        game_state: Option<GameState>,
+        cancel: CancellationToken,
+    ) -> Self {
+        LcuContext {
+            label: http_client.label.clone(),
+            http_client,
+            event,
+            previous_state,
+            game_state,
+            cancel,
+        }
+    }
+
+    /// 执行 future，回调被取消时放弃执行并返回 None
+    pub async fn run_until_cancelled<F: Future>(&self, future: F) -> Option<F::Output> {
+        tokio::select! {
+            biased;
+            _ = self.cancel.cancelled() => None,
+            output = future => Some(output),
+        }
+    }
+}
+
+/// websocket 的连接状态
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// 正在建立连接
+    #[default]
+    Connecting,
+    Connected,
+    /// 连接已断开或放弃重试
+    Disconnected,
+}
+
+/// 连接生命周期中的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    /// websocket 连接建立成功
+    Connected { port: i32 },
+    /// websocket 连接断开，reason 为关闭的原因
+    Disconnected { reason: Option<CloseReason> },
+    /// 第 attempt 次尝试重新连接
+    ReconnectAttempt { attempt: u32 },
+    /// 连接失败
+    Error { error: String },
+    /// 重新连接后客户端的端口发生了变化，通常是客户端重启了
+    CredentialsChanged { old_port: i32, new_port: i32 },
+}
+
+/// 最新的游戏状态、对局流程会话和连接状态，变化时通知所有订阅者
+struct StateChannels {
+    phase: watch::Sender<Option<GameState>>,
+    session: watch::Sender<Option<GameflowSession>>,
+    connection: watch::Sender<ConnectionStatus>,
+    connection_events: broadcast::Sender<ConnectionEvent>,
+}
+
+impl Default for StateChannels {
+    fn default() -> Self {
+        StateChannels {
+            phase: watch::Sender::new(None),
+            session: watch::Sender::new(None),
+            connection: watch::Sender::new(ConnectionStatus::default()),
+            connection_events: broadcast::channel(16).0,
+        }
+    }
+}
+
+impl StateChannels {
+    /// 连接断开后游戏状态和会话都不再可信
+    fn disconnected(&self) {
+        self.phase.send_replace(None);
+        self.session.send_replace(None);
+        self.connection.send_replace(ConnectionStatus::Disconnected);
+    }
+}
+
+/// 当前的游戏状态及其取消令牌，游戏状态变化时取消旧的令牌
+struct Phase {
+    game_state: Option<GameState>,
+    cancel: CancellationToken,
+}
+
+impl Default for Phase {
+    fn default() -> Self {
+        Phase {
+            game_state: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+/// 已注册的回调
+#[derive(Default)]
+struct Handlers {
+    /// 游戏状态变化的回调
+    game_flow: HashMap<GameFlowTrigger, Vec<Registration<Callback>>>,
+    /// 按 URI 模式注册的事件回调
+    events: EventRouter<Registration<RawEventCallback>>,
+    /// 连接事件的回调
+    connection: Vec<Registration<ConnectionCallback>>,
+    /// 各游戏状态回调的执行方式，未设置时依次执行
+    modes: HashMap<GameState, ExecutionMode>,
+    /// 下一个回调的 ID
+    next_id: u64,
+}
+
+impl Handlers {
+    fn next_id(&mut self) -> HandlerId {
+        self.next_id += 1;
+        HandlerId(self.next_id)
+    }
+
+    /// 所有已注册回调的信息，游戏状态回调在前，按 ID 排序
+    fn infos(&self) -> Vec<HandlerInfo> {
+        let mut game_flow: Vec<HandlerInfo> = self
+            .game_flow
+            .iter()
+            .flat_map(|(trigger, registrations)| registrations.iter().map(|r| r.info(trigger.to_string())))
+            .collect();
+        game_flow.sort_by_key(|info| info.id);
+        let events = self.events.routes().iter().map(|route| route.handler.info(route.pattern.as_str().to_string()));
+        let connection = self.connection.iter().map(|r| r.info("connection".to_string()));
+        game_flow.into_iter().chain(events).chain(connection).collect()
+    }
+
+    /// 设置满足条件的回调的启用状态，返回受影响的回调数量
+    fn set_enabled_where(&mut self, enabled: bool, predicate: impl Fn(HandlerId, &str) -> bool) -> usize {
+        let game_flow = self.game_flow.values_mut().flatten();
+        let events = self.events.routes_mut().map(|route| &mut route.handler);
+        let mut count = 0;
+        for registration in game_flow {
+            if predicate(registration.id, &registration.name) {
+                registration.enabled = enabled;
+                count += 1;
+            }
+        }
+        for registration in self.connection.iter_mut() {
+            if predicate(registration.id, &registration.name) {
+                registration.enabled = enabled;
+                count += 1;
+            }
+        }
+        for registration in events {
+            if predicate(registration.id, &registration.name) {
+                registration.enabled = enabled;
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// 移除满足条件的回调，返回需要取消订阅的事件主题，每个回调对应一个
+    fn remove_where(&mut self, predicate: impl Fn(HandlerId, &str) -> bool) -> Vec<String> {
+        let mut topics = Vec::new();
+        for registrations in self.game_flow.values_mut() {
+            let before = registrations.len();
+            registrations.retain(|r| !predicate(r.id, &r.name));
+            for _ in registrations.len()..before {
+                topics.push(Event::OnJsonApiEvent.topic(lcu_api::GAMEFLOW_PHASE));
+            }
+        }
+        self.game_flow.retain(|_, registrations| !registrations.is_empty());
+        let removed = self.events.remove_where(|r| predicate(r.id, &r.name));
+        topics.extend(removed.iter().map(|route| route.pattern.topic()));
+        topics
+    }
+
+    /// 移除满足条件的连接事件回调，返回移除的数量
+    fn remove_connection_where(&mut self, predicate: impl Fn(HandlerId, &str) -> bool) -> usize {
+        let before = self.connection.len();
+        self.connection.retain(|r| !predicate(r.id, &r.name));
+        before - self.connection.len()
+    }
+
+    /// 连接后需要主动同步的 URI，游戏状态和对局流程会话在前
+    fn sync_uris(&self) -> Vec<String> {
+        let mut uris: Vec<String> = STATE_URIS.iter().map(|uri| uri.to_string()).collect();
+        for route in self.events.routes() {
+            let uri = route.pattern.as_str();
+            if route.pattern.is_concrete() && !uris.iter().any(|u| u == uri) {
+                uris.push(uri.to_string());
+            }
+        }
+        uris
+    }
+
+    /// 所有回调需要订阅的事件主题，每个回调对应一个引用计数
+    fn topics(&self) -> Vec<String> {
+        let game_flow = self.game_flow.values().flatten().map(|_| Event::OnJsonApiEvent.topic(lcu_api::GAMEFLOW_PHASE));
+        game_flow.chain(self.events.topics()).collect()
+    }
+}
+
+/// 状态通道始终订阅的接口
+const STATE_URIS: [&str; 2] = [lcu_api::GAMEFLOW_PHASE, lcu_api::GAMEFLOW_SESSION];
+/// 每轮连接最多尝试的次数，超过后放弃
+const MAX_CONNECT_ATTEMPTS: u32 = 10;
+/// 首次连接失败后的重试间隔
+const CONNECT_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// 连接断开后的重连间隔
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// websocket 握手的超时时间，客户端启动中时可能接受连接但不响应
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct LcuClient {
     websocket: Arc<RwLock<Option<LcuWebsocket>>>,
-    game_flow_actions: Arc<RwLock<HashMap<GameState, Vec<Callback>>>>,
+    handlers: Arc<RwLock<Handlers>>,
     stop_notify: Arc<Notify>,
+    /// 连接信息的来源，可以在运行中替换，下次重连时生效
+    connect_source: Arc<RwLock<ConnectSource>>,
+    /// 连接信息被替换时通知，使重连不必等满间隔
+    source_changed: Arc<Notify>,
+    tls_mode: TlsMode,
+    http_client: Arc<RwLock<Option<Arc<LcuHttpClient>>>>,
+    label: Arc<RwLock<Option<String>>>,
+    /// 最近一次收到的游戏状态
+    phase: Arc<RwLock<Phase>>,
+    states: Arc<StateChannels>,
+    /// 客户端被丢弃时取消，结束后台任务
+    shutdown: CancellationToken,
 }
 
 impl LcuClient {
-    pub fn new() -> Self {
-        let listener = Arc::new(RwLock::new(None));
-        let c_listener = listener.clone();
-        let stop_notify = Arc::new(Notify::new());
-        let c_stop_notify = stop_notify.clone();
+    /// 创建客户端，此时不会连接，注册回调后调用 exec 开始连接并监听事件
+    pub fn new(connect_source: ConnectSource, tls_mode: TlsMode) -> Self {
+        LcuClient {
+            websocket: Arc::new(RwLock::new(None)),
+            handlers: Arc::new(RwLock::new(Handlers::default())),
+            stop_notify: Arc::new(Notify::new()),
+            connect_source: Arc::new(RwLock::new(connect_source)),
+            source_changed: Arc::new(Notify::new()),
+            tls_mode,
+            http_client: Arc::new(RwLock::new(None)),
+            label: Arc::new(RwLock::new(None)),
+            phase: Arc::new(RwLock::new(Phase::default())),
+            states: Arc::new(StateChannels::default()),
+            shutdown: CancellationToken::new(),
+        }
+    }
 
-        tokio::spawn(async move {
-            // 获取websocket连接，带重试逻辑
-            let mut my_listener = c_listener.write().await;
-            let mut retry_count = 0;
-            const MAX_RETRIES: u32 = 10;
-            
-            loop {
-                let l_stop_notify = c_stop_notify.clone();
-                if my_listener.is_none() {
-                    match LcuWebsocket::new(l_stop_notify).await {
-                        Ok(ws) => {
-                            *my_listener = Some(ws);
-                            println!("{} WebSocket连接建立成功", get_now_str());
-                            break;
-                        }
-                        Err(_) => {
-                            retry_count += 1;
-                            if retry_count >= MAX_RETRIES {
-                                println!("{} WebSocket连接失败{}次，停止尝试", get_now_str(), retry_count);
-                                break;
-                            }
-                            println!("{} WebSocket连接失败{}次，等待1秒后重试...", get_now_str(), retry_count);
-                            sleep(Duration::from_secs(1)).await;
-                        }
-                    }
-                } else {
-                    break;
-                }
+    /// 获取连接信息，创建HTTP客户端并建立websocket连接，只进行网络请求，订阅事件和替换HTTP客户端由调用方完成
+    async fn connect(connect_source: &ConnectSource, tls_mode: TlsMode) -> Result<(LcuWebsocket, Arc<LcuHttpClient>), Box<dyn Error + Send + Sync>> {
+        let connect_info = connect_source
+            .resolve()
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        let new_http_client = LcuHttpClient::new(&connect_info, tls_mode)?;
+        // 用召唤师名称区分多个客户端的日志，获取失败时使用端口
+        let summoner_name = new_http_client.get_summoner_name().await
+            .unwrap_or_else(|| format!("端口{}", connect_info.port));
+        let new_http_client = Arc::new(new_http_client.with_label(summoner_name.clone()));
+        let websocket = LcuWebsocket::new(connect_info, tls_mode, Some(summoner_name));
+        let websocket = tokio::time::timeout(CONNECT_TIMEOUT, websocket)
+            .await
+            .map_err(|_| "websocket handshake timed out")??;
+        Ok((websocket, new_http_client))
+    }
+
+    /// 只订阅状态通道和已注册的回调关心的事件，每个回调占一个引用计数
+    async fn subscribe_handlers(websocket: &LcuWebsocket, handlers: &RwLock<Handlers>) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for uri in STATE_URIS {
+            websocket.subscribe(&Event::OnJsonApiEvent.topic(uri)).await?;
+        }
+        for topic in handlers.read().await.topics() {
+            websocket.subscribe(&topic).await?;
+        }
+        Ok(())
+    }
+
+    /// 注册进入指定游戏状态时的回调，可以是函数或捕获了配置的闭包，名称为回调的类型名
+    pub async fn add_game_flow_action<H: Handler + 'static>(&self, game_state: GameState, handler: H) -> HandlerId {
+        self.on_enter(game_state, handler).await
+    }
+
+    /// 注册进入指定游戏状态时的回调，每次收到该游戏状态都会调用
+    pub async fn on_enter<H: Handler + 'static>(&self, game_state: GameState, handler: H) -> HandlerId {
+        self.add_game_flow_handler(GameFlowTrigger::Enter(game_state), HandlerOptions::default(), handler).await
+    }
+
+    /// 注册离开指定游戏状态时的回调，上下文中的 game_state 为新的游戏状态
+    pub async fn on_exit<H: Handler + 'static>(&self, game_state: GameState, handler: H) -> HandlerId {
+        self.add_game_flow_handler(GameFlowTrigger::Exit(game_state), HandlerOptions::default(), handler).await
+    }
+
+    /// 注册游戏状态从 from 变为 to 时的回调，例如 `InProgress -> WaitingForStats` 表示对局结束
+    pub async fn on_transition<H: Handler + 'static>(&self, from: GameState, to: GameState, handler: H) -> HandlerId {
+        self.add_game_flow_handler(GameFlowTrigger::Transition(from, to), HandlerOptions::default(), handler).await
+    }
+
+    /// 按选项注册游戏状态回调，名称可以重复，用于按名称启用、禁用或移除一组回调
+    pub async fn add_game_flow_handler<H: Handler + 'static>(&self, trigger: GameFlowTrigger, options: HandlerOptions, handler: H) -> HandlerId {
+        let callback: Callback = Arc::new(handler);
+        // 持有websocket的读锁，避免与建立连接时的订阅重复计数
+        let websocket = self.websocket.read().await;
+        let mut handlers = self.handlers.write().await;
+        let id = handlers.next_id();
+        handlers
+            .game_flow
+            .entry(trigger)
+            .or_default()
+            .push(Registration::new(id, options, std::any::type_name::<H>(), callback));
+        Self::subscribe_topic(websocket.as_ref(), &Event::OnJsonApiEvent.topic(lcu_api::GAMEFLOW_PHASE)).await;
+        id
+    }
+
+    /// 设置进入指定游戏状态时回调的执行方式，包括离开上一个状态和状态变化的回调
+    pub async fn set_execution_mode(&self, game_state: GameState, mode: ExecutionMode) {
+        self.handlers.write().await.modes.insert(game_state, mode);
+    }
+
+    /// 注册连接事件的回调，例如在界面上显示连接状态或在客户端重启后执行自定义的恢复逻辑
+    pub async fn on_connection_event<F, Fut>(&self, callback: F) -> HandlerId
+    where
+        F: Fn(ConnectionEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output=()> + Send + 'static,
+    {
+        self.on_connection_event_with(HandlerOptions::default(), callback).await
+    }
+
+    /// 按选项注册连接事件的回调
+    pub async fn on_connection_event_with<F, Fut>(&self, options: HandlerOptions, callback: F) -> HandlerId
+    where
+        F: Fn(ConnectionEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output=()> + Send + 'static,
+    {
+        let callback: ConnectionCallback = Arc::new(move |event| Box::pin(callback(event)) as BoxFuture);
+        let mut handlers = self.handlers.write().await;
+        let id = handlers.next_id();
+        handlers.connection.push(Registration::new(id, options, std::any::type_name::<F>(), callback));
+        id
+    }
+
+    /// 订阅连接事件，不需要注册回调
+    pub fn connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.states.connection_events.subscribe()
+    }
+
+    /// 移除回调，回调不存在时返回 false
+    pub async fn remove_handler(&self, id: HandlerId) -> bool {
+        self.remove_handlers_where(|handler_id, _| handler_id == id).await > 0
+    }
+
+    /// 移除指定名称的所有回调，返回移除的数量
+    pub async fn remove_handlers_by_name(&self, name: &str) -> usize {
+        self.remove_handlers_where(|_, handler_name| handler_name == name).await
+    }
+
+    async fn remove_handlers_where(&self, predicate: impl Fn(HandlerId, &str) -> bool) -> usize {
+        let websocket = self.websocket.read().await;
+        let (topics, connection_count) = {
+            let mut handlers = self.handlers.write().await;
+            let connection_count = handlers.remove_connection_where(&predicate);
+            (handlers.remove_where(&predicate), connection_count)
+        };
+        for topic in &topics {
+            Self::unsubscribe_topic(websocket.as_ref(), topic).await;
+        }
+        topics.len() + connection_count
+    }
+
+    /// 启用或禁用回调，禁用的回调保留订阅但不会被调用，回调不存在时返回 false
+    pub async fn set_handler_enabled(&self, id: HandlerId, enabled: bool) -> bool {
+        self.handlers.write().await.set_enabled_where(enabled, |handler_id, _| handler_id == id) > 0
+    }
+
+    /// 启用或禁用指定名称的所有回调，返回受影响的数量
+    pub async fn set_handlers_enabled_by_name(&self, name: &str, enabled: bool) -> usize {
+        self.handlers.write().await.set_enabled_where(enabled, |_, handler_name| handler_name == name)
+    }
+
+    /// 所有已注册回调的信息
+    pub async fn handlers(&self) -> Vec<HandlerInfo> {
+        self.handlers.read().await.infos()
+    }
+
+    /// 注册事件回调，处理所有类型的事件，详见 `on_event`
+    ///
+    /// 例如 `client.on::<GameflowSession>(lcu_api::GAMEFLOW_SESSION, callback)`
+    pub async fn on<T, F, Fut>(&self, pattern: &str, callback: F) -> HandlerId
+    where
+        T: DeserializeOwned + Send + 'static,
+        F: Fn(LcuEvent<T>, LcuContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output=()> + Send + 'static,
+    {
+        self.on_event(pattern, &[], callback).await
+    }
+
+    /// 注册匹配 URI 模式的事件回调，event_types 为空时处理所有类型的事件
+    ///
+    /// 模式中 `{name}` 匹配任意一段并作为路径参数传给回调，`*` 匹配任意一段，位于末尾时匹配剩余的所有段，
+    /// 例如 `/lol-champ-select/v1/summoners/{cellId}`、`/lol-chat/v1/conversations/*`。
+    /// 事件数据反序列化为 T 后传给回调，反序列化失败时打印错误并跳过。回调的名称为其类型名
+    pub async fn on_event<T, F, Fut>(&self, pattern: &str, event_types: &[LcuEventType], callback: F) -> HandlerId
+    where
+        T: DeserializeOwned + Send + 'static,
+        F: Fn(LcuEvent<T>, LcuContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output=()> + Send + 'static,
+    {
+        self.on_event_with(pattern, event_types, HandlerOptions::default(), callback).await
+    }
+
+    /// 按选项注册事件回调，同一事件的多个回调按优先级依次执行
+    pub async fn on_event_with<T, F, Fut>(&self, pattern: &str, event_types: &[LcuEventType], options: HandlerOptions, callback: F) -> HandlerId
+    where
+        T: DeserializeOwned + Send + 'static,
+        F: Fn(LcuEvent<T>, LcuContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output=()> + Send + 'static,
+    {
+        let websocket = self.websocket.read().await;
+        let mut handlers = self.handlers.write().await;
+        let id = handlers.next_id();
+        let registration = Registration::new(id, options, std::any::type_name::<F>(), erase_event_callback(callback));
+        handlers.events.add(pattern, event_types, registration);
+        Self::subscribe_topic(websocket.as_ref(), &UriPattern::parse(pattern).topic()).await;
+        id
+    }
+
+    /// 为新注册的回调订阅事件主题，未连接时在建立连接后统一订阅
+    async fn subscribe_topic(websocket: Option<&LcuWebsocket>, topic: &str) {
+        if let Some(websocket) = websocket {
+            if let Err(e) = websocket.subscribe(topic).await {
+                println!("{} {}订阅 {} 失败: {}", get_now_str(), label_prefix(websocket.label.as_deref()), topic, e);
             }
-        });
+        }
+    }
 
-        let actions = Arc::new(RwLock::new(HashMap::new()));
-        LcuClient {
-            websocket: listener,
-            game_flow_actions: actions,
-            stop_notify,
+    async fn unsubscribe_topic(websocket: Option<&LcuWebsocket>, topic: &str) {
+        if let Some(websocket) = websocket {
+            if let Err(e) = websocket.unsubscribe(topic).await {
+                println!("{} {}取消订阅 {} 失败: {}", get_now_str(), label_prefix(websocket.label.as_deref()), topic, e);
+            }
+        }
+    }
+
+    /// websocket 关闭的原因，用于判断是否需要以及如何重连，连接未关闭或从未建立时为 None
+    pub async fn close_reason(&self) -> Option<CloseReason> {
+        match self.websocket.read().await.as_ref() {
+            Some(websocket) => websocket.close_reason().await,
+            None => None,
+        }
+    }
+
+    /// 通过 websocket 调用 LCU 的过程，未连接时返回 `LcuError::Disconnected`
+    pub async fn call(&self, procedure: &str, args: Vec<serde_json::Value>) -> Result<serde_json::Value, LcuError> {
+        match self.websocket.read().await.as_ref() {
+            Some(websocket) => websocket.call(procedure, args).await,
+            None => Err(LcuError::Disconnected),
         }
     }
 
-    pub async fn add_game_flow_action(&self, game_state: GameState, callback: Callback) {
-        let mut game_flow_actions = self.game_flow_actions.write().await;
-        let res = game_flow_actions.get_mut(&game_state);
-        if let Some(callback_list) = res {
-            callback_list.push(callback);
-        } else {
-            let mut callback_list = Vec::new();
-            callback_list.push(callback);
-            game_flow_actions.insert(game_state, callback_list);
+    /// 订阅事件主题，未连接时返回错误，重连后需要重新订阅
+    pub async fn subscribe(&self, topic: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.websocket.read().await.as_ref() {
+            Some(websocket) => websocket.subscribe(topic).await,
+            None => Err(From::from("websocket is not connected")),
         }
     }
 
-    pub async fn remove_game_flow_action(&self, game_state: GameState, index: usize) {
-        let mut game_flow_actions = self.game_flow_actions.write().await;
-        if let Some(callback_list) = game_flow_actions.get_mut(&game_state) {
-            callback_list.remove(index);
+    /// 取消订阅事件主题
+    pub async fn unsubscribe(&self, topic: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.websocket.read().await.as_ref() {
+            Some(websocket) => websocket.unsubscribe(topic).await,
+            None => Err(From::from("websocket is not connected")),
         }
     }
 
@@ -91,168 +516,389 @@ impl LcuClient {
         self.stop_notify.clone()
     }
 
+    /// 当前连接的客户端信息，包括 pid、安装目录、区域、语言以及 Riot Client 的端口和 token，未连接时返回 None
+    pub async fn connect_info(&self) -> Option<LolClientConnectInfo> {
+        let websocket = self.websocket.read().await;
+        websocket.as_ref().map(|ws| ws.connect_info.clone())
+    }
+
+    /// 当前连接的HTTP客户端，未连接时返回 None，重连后会被整体替换
+    pub async fn http_client(&self) -> Option<Arc<LcuHttpClient>> {
+        self.http_client.read().await.clone()
+    }
+
+    /// 客户端标签，连接成功后为当前登录的召唤师名称
+    pub async fn label(&self) -> Option<String> {
+        self.label.read().await.clone()
+    }
+
+    /// 当前的游戏状态，尚未收到或连接已断开时为 None
+    pub fn current_phase(&self) -> Option<GameState> {
+        self.states.phase.borrow().clone()
+    }
+
+    /// 等待进入指定的游戏状态，已处于该状态时立即返回，超时返回 false
+    pub async fn wait_for_phase(&self, game_state: GameState, timeout: Duration) -> bool {
+        let mut rx = self.states.phase.subscribe();
+        let wait = rx.wait_for(|phase| phase.as_ref() == Some(&game_state));
+        tokio::time::timeout(timeout, wait).await.is_ok_and(|result| result.is_ok())
+    }
+
+    /// 游戏状态的变化，先产出当前状态，之后每次变化产出一次，连续的变化可能只产出最新的状态
+    pub fn phase_stream(&self) -> impl Stream<Item=Option<GameState>> {
+        let rx = self.states.phase.subscribe();
+        stream::unfold((rx, true), |(mut rx, first)| async move {
+            if !first && rx.changed().await.is_err() {
+                return None;
+            }
+            let phase = rx.borrow_and_update().clone();
+            Some((phase, (rx, false)))
+        })
+    }
+
+    /// 订阅游戏状态的变化
+    pub fn watch_phase(&self) -> watch::Receiver<Option<GameState>> {
+        self.states.phase.subscribe()
+    }
+
+    /// 当前的对局流程会话，不在对局流程中或连接已断开时为 None
+    pub fn session(&self) -> Option<GameflowSession> {
+        self.states.session.borrow().clone()
+    }
+
+    /// 订阅对局流程会话的变化
+    pub fn watch_session(&self) -> watch::Receiver<Option<GameflowSession>> {
+        self.states.session.subscribe()
+    }
+
+    /// 当前的连接状态
+    pub fn connection_status(&self) -> ConnectionStatus {
+        *self.states.connection.borrow()
+    }
+
+    /// 订阅连接状态的变化
+    pub fn watch_connection_status(&self) -> watch::Receiver<ConnectionStatus> {
+        self.states.connection.subscribe()
+    }
+
     pub fn get_event_listener(&self) -> Arc<RwLock<Option<LcuWebsocket>>> {
         self.websocket.clone()
     }
 
+    /// 替换连接信息的来源，例如客户端重启后端口和 token 发生了变化，
+    /// 当前连接不受影响，正在等待重连时立即使用新的连接信息重试
+    pub async fn set_connect_source(&self, connect_source: ConnectSource) {
+        *self.connect_source.write().await = connect_source;
+        self.source_changed.notify_one();
+    }
+
+    /// 关闭当前连接，exec 启动的后台任务会按连接断开处理并重新连接
     pub async fn reconnect(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // 重置HTTP客户端
-        reset_lcu_http_client()?;
-        
-        // 重新创建WebSocket连接
-        let mut websocket = self.websocket.write().await;
-        *websocket = None;
-        
-        // 尝试重新连接
-        let stop_notify = self.stop_notify.clone();
-        loop {
-            match LcuWebsocket::new(stop_notify.clone()).await {
-                Ok(new_websocket) => {
-                    *websocket = Some(new_websocket);
-                    println!("{} 重新连接成功！", get_now_str());
-                    return Ok(());
-                }
-                Err(e) => {
-                    println!("{} 重新连接失败: {}，等待5秒后重试...", get_now_str(), e);
-                    sleep(Duration::from_secs(5)).await;
-                }
-            }
+        match self.websocket.read().await.as_ref() {
+            Some(websocket) => websocket.sender.close().await,
+            None => Err(From::from("websocket is not connected")),
         }
     }
 
+    /// 在后台连接客户端并监听事件，连接断开后自动重连，
+    /// 多次连接失败或客户端被丢弃后结束，并通过 stop_notify 通知
     pub async fn exec(&self) {
-        let c_listener = self.websocket.clone();
+        let listener = self.websocket.clone();
         let notify = self.get_stop_notify();
-        let actions = self.game_flow_actions.clone();
+        let connect_source = self.connect_source.clone();
+        let source_changed = self.source_changed.clone();
+        let tls_mode = self.tls_mode;
+        let handlers = self.handlers.clone();
+        let http_client = self.http_client.clone();
+        let label = self.label.clone();
+        let phase = self.phase.clone();
+        let states = self.states.clone();
+        let shutdown = self.shutdown.clone();
         tokio::spawn(async move {
-            let listener = c_listener;
-            let mut rx;
-            let mut connection_attempts = 0;
-            const MAX_CONNECTION_ATTEMPTS: u32 = 30; // 最多尝试30秒
-            
-            // 尝试连接本地英雄联盟客户端
+            // 上一次连接的端口，首次连接前为 None
+            let mut last_port: Option<i32> = None;
             loop {
-                let listener = listener.read().await;
-                if let Some(a) = listener.as_ref() {
-                    rx = a.data.read().await.subscribe();
-                    println!("{} 成功订阅游戏事件", get_now_str());
+                // 首次连接失败时每秒重试，断开后每5秒尝试重连一次
+                let retry_delay = if last_port.is_some() { RECONNECT_DELAY } else { CONNECT_RETRY_DELAY };
+                let mut failures = 0;
+                let mut attempt = 0;
+                let connected = loop {
+                    if last_port.is_some() || failures > 0 {
+                        attempt += 1;
+                        Self::emit(&handlers, &states, &label, ConnectionEvent::ReconnectAttempt { attempt }).await;
+                    }
+                    // 网络请求在加锁前完成，未响应的客户端不会阻塞注册回调、调用接口等操作
+                    let source = connect_source.read().await.clone();
+                    let result = match Self::connect(&source, tls_mode).await {
+                        Ok((ws, new_http_client)) => {
+                            // 持有websocket的写锁订阅，避免与注册回调时的订阅重复计数
+                            let mut websocket = listener.write().await;
+                            match Self::subscribe_handlers(&ws, &handlers).await {
+                                Ok(()) => {
+                                    // 两者都成功后才替换旧的HTTP客户端
+                                    *label.write().await = new_http_client.label.clone();
+                                    *http_client.write().await = Some(new_http_client);
+                                    let connection = (ws.connect_info.port, ws.data.read().await.subscribe(), ws.closed());
+                                    *websocket = Some(ws);
+                                    Ok(connection)
+                                }
+                                Err(e) => Err(e),
+                            }
+                        }
+                        Err(e) => Err(e),
+                    };
+                    match result {
+                        Ok(connection) => break Some(connection),
+                        Err(e) => {
+                            failures += 1;
+                            Self::emit(&handlers, &states, &label, ConnectionEvent::Error { error: e.to_string() }).await;
+                            if failures >= MAX_CONNECT_ATTEMPTS {
+                                println!("{} {}WebSocket连接失败{}次，停止尝试", get_now_str(), format_label(&label).await, failures);
+                                break None;
+                            }
+                            println!("{} {}WebSocket连接失败{}次: {}，等待{}秒后重试...", get_now_str(), format_label(&label).await, failures, e, retry_delay.as_secs());
+                            tokio::select! {
+                                _ = shutdown.cancelled() => break None,
+                                // 新的连接信息重新计算失败次数
+                                _ = source_changed.notified() => failures = 0,
+                                _ = sleep(retry_delay) => {}
+                            }
+                        }
+                    }
+                };
+                let Some((port, mut rx, closed)) = connected else {
+                    states.connection.send_replace(ConnectionStatus::Disconnected);
                     break;
+                };
+                states.connection.send_replace(ConnectionStatus::Connected);
+                println!("{} {}WebSocket连接建立成功", get_now_str(), format_label(&label).await);
+                if let Some(old_port) = last_port.filter(|old_port| *old_port != port) {
+                    Self::emit(&handlers, &states, &label, ConnectionEvent::CredentialsChanged { old_port, new_port: port }).await;
                 }
-                
-                connection_attempts += 1;
-                if connection_attempts >= MAX_CONNECTION_ATTEMPTS {
-                    println!("{} 连接游戏失败，超过最大重试次数", get_now_str());
-                    notify.notify_one();
-                    return;
+                Self::emit(&handlers, &states, &label, ConnectionEvent::Connected { port }).await;
+                last_port = Some(port);
+
+                // 客户端可能已经处于准备确认、英雄选择等状态，主动同步一次当前状态，
+                // 同步在独立的任务中进行，不阻塞实时事件
+                // 事件回调不由游戏状态触发，只在连接断开或客户端被丢弃时取消
+                let connection_cancel = shutdown.child_token();
+                let (sync_tx, mut sync_rx) = mpsc::channel(STATE_URIS.len());
+                if let Some(c) = http_client.read().await.clone() {
+                    let handlers = handlers.clone();
+                    tokio::spawn(async move { Self::sync_initial_state(&handlers, &c, sync_tx).await });
                 }
-                
-                // 如果连接失败，则等待1s后重新连接
-                sleep(Duration::from_secs(1)).await;
-            }
-            
-            // 监听游戏事件
-            loop {
-                match rx.recv().await {
-                    Ok(lcu_data) => {
-                        let a = actions.clone();
-                        Self::match_data(a, lcu_data).await;
-                    }
-                    Err(broadcast::error::RecvError::Lagged(_)) => {
-                        // 如果落后了，继续接收新消息
-                        println!("{} 消息处理落后，跳过一些消息", get_now_str());
-                        continue;
-                    }
-                    Err(broadcast::error::RecvError::Closed) => {
-                        // 连接已关闭，可能是游戏重启了
-                        println!("{} WebSocket连接已关闭，可能是游戏重启了", get_now_str());
-                        break;
+
+                // 本次连接中已收到实时事件的 URI，同步得到的值可能比实时事件旧，这些 URI 不再使用同步的值
+                let mut seen_uris = HashSet::new();
+                // 监听游戏事件，连接关闭或客户端被丢弃时结束，优先处理已收到的实时事件
+                loop {
+                    let lcu_data = tokio::select! {
+                        biased;
+                        _ = shutdown.cancelled() => break,
+                        result = rx.recv() => match result {
+                            Ok(lcu_data) => {
+                                seen_uris.insert(lcu_data.uri.clone());
+                                lcu_data
+                            }
+                            Err(broadcast::error::RecvError::Lagged(_)) => {
+                                // 如果落后了，继续接收新消息
+                                println!("{} {}消息处理落后，跳过一些消息", get_now_str(), format_label(&label).await);
+                                continue;
+                            }
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        },
+                        Some(lcu_data) = sync_rx.recv() => {
+                            if seen_uris.contains(&lcu_data.uri) {
+                                continue;
+                            }
+                            lcu_data
+                        }
+                        _ = closed.cancelled() => {
+                            // 连接已关闭，可能是游戏重启了
+                            println!("{} {}WebSocket连接已关闭，可能是游戏重启了", get_now_str(), format_label(&label).await);
+                            break;
+                        }
+                    };
+                    let h = handlers.clone();
+                    let c = http_client.read().await.clone();
+                    if let Some(c) = c {
+                        Self::match_data(h, c, &phase, &connection_cancel, &states, lcu_data).await;
                     }
                 }
+                // 连接断开后取消仍在执行的回调，重连后重新从未知状态开始
+                let old_phase = std::mem::take(&mut *phase.write().await);
+                old_phase.cancel.cancel();
+                connection_cancel.cancel();
+                // 连接关闭后游戏状态和会话不再可信，先重置状态通道再通知回调
+                states.disconnected();
+                let reason = match listener.read().await.as_ref() {
+                    Some(websocket) => websocket.close_reason().await,
+                    None => None,
+                };
+                Self::emit(&handlers, &states, &label, ConnectionEvent::Disconnected { reason }).await;
+                if shutdown.is_cancelled() {
+                    break;
+                }
+                states.connection.send_replace(ConnectionStatus::Connecting);
             }
             notify.notify_one();
         });
     }
 
-    async fn match_data(actions: Arc<RwLock<HashMap<GameState, Vec<Callback>>>>, lcu_data: LcuData) {
-        match lcu_data.uri.as_str() {
-            // 游戏状态
-            lcu_api::GAMEFLOW_PHASE => {
-                let state = lcu_data.data.as_str().unwrap();
-                let game_state = GameState::from_value(state);
-                if game_state == GameState::EndOfGame {
-                    println!("{} {:?}\n\n\n",get_now_str(), &lcu_data);
-                }
-                let actions = actions.read().await;
-                let res = actions.get(&game_state);
-                if let Some(callbacks) = res {
-                    for callback in callbacks {
-                        callback().await;
+    /// 广播连接事件，并在独立的任务中依次执行连接事件的回调
+    async fn emit(handlers: &RwLock<Handlers>, states: &StateChannels, label: &RwLock<Option<String>>, event: ConnectionEvent) {
+        // 没有订阅者时发送失败，可以忽略
+        let _ = states.connection_events.send(event.clone());
+        let handlers = handlers.read().await;
+        let jobs: Vec<_> = handlers
+            .connection
+            .iter()
+            .filter(|r| r.enabled)
+            .map(|registration| {
+                let callback = registration.handler.clone();
+                let event = event.clone();
+                registration.job(Box::pin(async move { callback(event).await }))
+            })
+            .collect();
+        if !jobs.is_empty() {
+            tokio::spawn(run_jobs(jobs, ExecutionMode::Sequential, format_label(label).await));
+        }
+    }
+
+    /// 获取已订阅资源的当前值并作为 Update 事件发送，使回调能够处理连接前就已进入的状态，
+    /// 只同步游戏状态和不含参数、通配符的 URI，资源不存在时跳过，已收到实时事件的 URI 由接收端丢弃
+    async fn sync_initial_state(handlers: &RwLock<Handlers>, http_client: &LcuHttpClient, data: mpsc::Sender<LcuData>) {
+        let uris = handlers.read().await.sync_uris();
+        for uri in uris {
+            match http_client.get::<serde_json::Value>(&uri).await {
+                Ok(value) => {
+                    let lcu_data = LcuData {
+                        data: value,
+                        event_type: LcuEventType::Update,
+                        uri,
+                    };
+                    // 接收端已丢弃时说明连接已关闭，不需要继续同步
+                    if data.send(lcu_data).await.is_err() {
+                        return;
                     }
                 }
-            }
-            _ => {
-                // TODO：暂未实现其他状态的功能
+                Err(e) if e.status() == Some(404) => {}
+                Err(e) => println!("{} {}同步 {} 失败: {}", get_now_str(), label_prefix(http_client.label.as_deref()), uri, e),
             }
         }
     }
-}
-
-static mut LCU_HTTP_CLIENT: Option<Arc<RwLock<LcuHttpClient>>> = None;
-static mut ONCE: std::sync::Once = std::sync::Once::new();
 
-pub(in crate::lcu) struct LcuHttpClient {
-    pub(in crate::lcu) client: Client,
-    pub(in crate::lcu) url: String,
-}
+    async fn match_data(
+        handlers: Arc<RwLock<Handlers>>,
+        http_client: Arc<LcuHttpClient>,
+        phase: &RwLock<Phase>,
+        connection_cancel: &CancellationToken,
+        states: &StateChannels,
+        lcu_data: LcuData,
+    ) {
+        let prefix = label_prefix(http_client.label.as_deref());
+        // 对局流程会话，结束后会被删除
+        if lcu_data.uri == lcu_api::GAMEFLOW_SESSION {
+            match serde_json::from_value::<Option<GameflowSession>>(lcu_data.data.clone()) {
+                Ok(session) if lcu_data.event_type != LcuEventType::Delete => states.session.send_replace(session),
+                Ok(_) => states.session.send_replace(None),
+                Err(e) => {
+                    println!("{} {}解析对局流程会话失败: {}", get_now_str(), prefix, e);
+                    None
+                }
+            };
+        }
+        // 游戏状态
+        let mut new_state = None;
+        if lcu_data.uri == lcu_api::GAMEFLOW_PHASE {
+            match lcu_data.data.as_str() {
+                Some(state) => new_state = Some(GameState::from(state)),
+                None => println!("{} {}解析游戏状态失败: {}", get_now_str(), prefix, lcu_data.data),
+            }
+        }
+        let phase_changed = new_state.is_some();
+        let (previous_state, current_state, cancel) = {
+            let mut phase = phase.write().await;
+            let previous_state = phase.game_state.clone();
+            if phase_changed && new_state != previous_state {
+                // 离开旧状态，取消由旧状态触发且仍在执行的回调
+                phase.cancel.cancel();
+                phase.cancel = CancellationToken::new();
+                phase.game_state = new_state;
+                states.phase.send_replace(phase.game_state.clone());
+            }
+            (previous_state, phase.game_state.clone(), phase.cancel.clone())
+        };
+        let new_context = |cancel: &CancellationToken| {
+            LcuContext::new(http_client.clone(), lcu_data.clone(), previous_state.clone(), current_state.clone(), cancel.child_token())
+        };
 
-pub(in crate::lcu) fn get_lcu_http_client() -> Arc<RwLock<LcuHttpClient>> {
-    unsafe {
-        if LCU_HTTP_CLIENT.is_some() {
-            return LCU_HTTP_CLIENT.as_ref().unwrap().clone();
-        } else {
-            let mut headers = header::HeaderMap::new();
-            headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json; charset=utf-8"));
-            headers.insert(header::ACCEPT, header::HeaderValue::from_static("application/json; charset=utf-8"));
-            let connect_info = get_lol_client_connect_info().unwrap();
-            let auth = gen_lcu_auth("riot", &connect_info.token.clone());
-            headers.insert(header::AUTHORIZATION, header::HeaderValue::from_str(auth.as_str()).unwrap());
-            let client = Client::builder()
-                .default_headers(headers)
-                .danger_accept_invalid_certs(true)
-                .build().unwrap();
-            let url = format!("https://{}:{}", "127.0.0.1", connect_info.port);
-            let instance = LcuHttpClient { client, url };
-            let _ = LCU_HTTP_CLIENT.insert(Arc::new(RwLock::new(instance)));
-            LCU_HTTP_CLIENT.as_ref().unwrap().clone()
+        let handlers = handlers.read().await;
+        // 回调在独立的任务中执行，不阻塞后续事件
+        let mut event_jobs = Vec::new();
+        for (registration, params) in handlers.events.route(&lcu_data) {
+            if !registration.enabled {
+                continue;
+            }
+            let callback = registration.handler.clone();
+            let lcu_data = lcu_data.clone();
+            let ctx = new_context(connection_cancel);
+            let prefix = prefix.clone();
+            event_jobs.push(registration.job(Box::pin(async move {
+                match callback(&lcu_data, params, ctx) {
+                    Ok(future) => future.await,
+                    Err(e) => println!("{} {}解析 {} 事件失败: {}", get_now_str(), prefix, lcu_data.uri, e),
+                }
+            })));
+        }
+        if !event_jobs.is_empty() {
+            tokio::spawn(run_jobs(event_jobs, ExecutionMode::Sequential, prefix.clone()));
+        }
+        if !phase_changed {
+            return;
+        }
+        let Some(game_state) = current_state.as_ref() else {
+            return;
+        };
+        if let GameState::Unknown(name) = game_state {
+            println!("{} {}未知的游戏状态: {}", get_now_str(), prefix, name);
+        }
+        if *game_state == GameState::EndOfGame {
+            println!("{} {}{:?}\n\n\n", get_now_str(), prefix, &lcu_data);
+        }
+        // 先离开旧状态，再进入新状态，重复收到同一状态时只触发进入
+        let mut triggers = Vec::new();
+        if let Some(previous_state) = previous_state.as_ref().filter(|state| *state != game_state) {
+            triggers.push(GameFlowTrigger::Exit(previous_state.clone()));
+            triggers.push(GameFlowTrigger::Transition(previous_state.clone(), game_state.clone()));
+        }
+        triggers.push(GameFlowTrigger::Enter(game_state.clone()));
+        let mut game_flow_jobs = Vec::new();
+        for trigger in &triggers {
+            let Some(registrations) = handlers.game_flow.get(trigger) else {
+                continue;
+            };
+            for registration in registrations.iter().filter(|r| r.enabled) {
+                let handler = registration.handler.clone();
+                let ctx = new_context(&cancel);
+                game_flow_jobs.push(registration.job(Box::pin(async move { handler.call(ctx).await })));
+            }
+        }
+        if !game_flow_jobs.is_empty() {
+            let mode = handlers.modes.get(game_state).copied().unwrap_or_default();
+            tokio::spawn(run_jobs(game_flow_jobs, mode, prefix));
         }
     }
 }
 
-pub(in crate::lcu) fn reset_lcu_http_client() -> Result<(), Box<dyn Error + Send + Sync>> {
-    unsafe {
-        if LCU_HTTP_CLIENT.is_some() {
-            // 获取新的连接信息
-            let connect_info = match get_lol_client_connect_info() {
-                Ok(info) => info,
-                Err(e) => return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, e.to_string())))
-            };
-            let mut headers = header::HeaderMap::new();
-            headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json; charset=utf-8"));
-            headers.insert(header::ACCEPT, header::HeaderValue::from_static("application/json; charset=utf-8"));
-            let auth = gen_lcu_auth("riot", &connect_info.token);
-            headers.insert(header::AUTHORIZATION, header::HeaderValue::from_str(auth.as_str()).unwrap());
-            let client = Client::builder()
-                .default_headers(headers)
-                .danger_accept_invalid_certs(true)
-                .build()?;
-            let url = format!("https://{}:{}", "127.0.0.1", connect_info.port);
-            let instance = LcuHttpClient { client, url };
-            LCU_HTTP_CLIENT = Some(Arc::new(RwLock::new(instance)));
-            Ok(())
-        } else {
-            // 如果还没有初始化，直接创建新的
-            let _ = get_lcu_http_client();
-            Ok(())
-        }
+impl Drop for LcuClient {
+    fn drop(&mut self) {
+        // 结束事件循环，使后台任务释放持有的连接
+        self.shutdown.cancel();
     }
 }
+
+/// 日志中的客户端标签，未获取到召唤师名称时为空
+async fn format_label(label: &RwLock<Option<String>>) -> String {
+    label_prefix(label.read().await.as_deref())
+}
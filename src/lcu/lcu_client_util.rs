@@ -1,35 +1,45 @@
 use crate::lcu::constants::lcu_api;
-use crate::lcu::lcu_client::get_lcu_http_client;
-use crate::lcu::utils::get_now_str;
+use crate::lcu::lcu_client::LcuContext;
+use crate::lcu::retry::RetryPolicy;
+use crate::lcu::utils::{get_now_str, label_prefix};
+use reqwest::Method;
+use serde::de::IgnoredAny;
 use std::future::Future;
 use std::pin::Pin;
 
 /// 接受对局
-pub fn accept_game() -> Pin<Box<dyn Future<Output=()> + Send>> {
-    Box::pin(async {
-        let instance = get_lcu_http_client();
-        let lcu_client = instance.read().await;
-        lcu_client.client.post(format!("{}{}", lcu_client.url.clone(), lcu_api::GAME_ACCEPT)).body("").send().await.unwrap();
-        println!("{} 已自动接受对局。", get_now_str());
+pub fn accept_game(ctx: LcuContext) -> Pin<Box<dyn Future<Output=()> + Send>> {
+    Box::pin(async move {
+        // 客户端刚弹出准备确认时接口可能还未就绪，需要快速重试，有人拒绝后放弃
+        let policy = RetryPolicy::critical();
+        let request = ctx.http_client
+            .request_with_policy::<_, IgnoredAny>(Method::POST, lcu_api::GAME_ACCEPT, &(), &policy);
+        match ctx.run_until_cancelled(request).await {
+            Some(Ok(_)) => println!("{} {}已自动接受对局。", get_now_str(), label_prefix(ctx.label.as_deref())),
+            Some(Err(e)) => println!("{} {}自动接受对局失败: {}", get_now_str(), label_prefix(ctx.label.as_deref()), e),
+            None => println!("{} {}准备确认已结束，放弃接受对局。", get_now_str(), label_prefix(ctx.label.as_deref())),
+        }
     })
 }
 
 /// 再来一局
-pub fn play_again() -> Pin<Box<dyn Future<Output=()> + Send>> {
-    Box::pin(async {
-        let instance = get_lcu_http_client();
-        let lcu_client = instance.read().await;
-        lcu_client.client.post(format!("{}{}", lcu_client.url.clone(), lcu_api::PLAY_AGAIN)).body("").send().await.unwrap();
-        println!("{} 已自动再来一局。", get_now_str());
+pub fn play_again(ctx: LcuContext) -> Pin<Box<dyn Future<Output=()> + Send>> {
+    Box::pin(async move {
+        match ctx.run_until_cancelled(ctx.http_client.post::<_, IgnoredAny>(lcu_api::PLAY_AGAIN, &())).await {
+            Some(Ok(_)) => println!("{} {}已自动再来一局。", get_now_str(), label_prefix(ctx.label.as_deref())),
+            Some(Err(e)) => println!("{} {}自动再来一局失败: {}", get_now_str(), label_prefix(ctx.label.as_deref()), e),
+            None => println!("{} {}游戏状态已变化，放弃再来一局。", get_now_str(), label_prefix(ctx.label.as_deref())),
+        }
     })
 }
 
 /// 寻找对局
-pub fn search_game() -> Pin<Box<dyn Future<Output=()> + Send>> {
-    Box::pin(async {
-        let instance = get_lcu_http_client();
-        let lcu_client = instance.read().await;
-        lcu_client.client.post(format!("{}{}", lcu_client.url.clone(), lcu_api::GAME_SEARCH)).body("").send().await.unwrap();
-        println!("{} 已自动寻找对局。", get_now_str());
+pub fn search_game(ctx: LcuContext) -> Pin<Box<dyn Future<Output=()> + Send>> {
+    Box::pin(async move {
+        match ctx.run_until_cancelled(ctx.http_client.post::<_, IgnoredAny>(lcu_api::GAME_SEARCH, &())).await {
+            Some(Ok(_)) => println!("{} {}已自动寻找对局。", get_now_str(), label_prefix(ctx.label.as_deref())),
+            Some(Err(e)) => println!("{} {}自动寻找对局失败: {}", get_now_str(), label_prefix(ctx.label.as_deref()), e),
+            None => println!("{} {}游戏状态已变化，放弃寻找对局。", get_now_str(), label_prefix(ctx.label.as_deref())),
+        }
     })
 }
\ No newline at end of file
@@ -0,0 +1,137 @@
+use crate::lcu::constants::lcu_api;
+use crate::lcu::error::LcuError;
+use crate::lcu::retry::RetryPolicy;
+use crate::lcu::tls::TlsMode;
+use crate::lcu::utils::{gen_lcu_auth, get_now_str, label_prefix, LolClientConnectInfo};
+use reqwest::{header, Client, Method};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::time::Duration;
+
+/// 单次请求的超时时间，客户端启动中时可能接受连接但不响应
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// 建立连接的超时时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct LcuHttpClient {
+    pub client: Client,
+    pub url: String,
+    /// 默认的重试策略，get/post 等方法使用
+    pub retry_policy: RetryPolicy,
+    /// 日志中区分多个客户端的标签，通常为召唤师名称
+    pub label: Option<String>,
+}
+
+impl LcuHttpClient {
+    /// 根据连接信息创建HTTP客户端，连接信息无效时返回错误
+    pub fn new(connect_info: &LolClientConnectInfo, tls_mode: TlsMode) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json; charset=utf-8"));
+        headers.insert(header::ACCEPT, header::HeaderValue::from_static("application/json; charset=utf-8"));
+        let auth = gen_lcu_auth("riot", &connect_info.token);
+        headers.insert(header::AUTHORIZATION, header::HeaderValue::from_str(auth.as_str())?);
+        let builder = Client::builder()
+            .default_headers(headers)
+            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT);
+        let client = tls_mode.configure_http(builder)?.build()?;
+        let url = format!("https://{}:{}", connect_info.host(), connect_info.port);
+        Ok(LcuHttpClient { client, url, retry_policy: RetryPolicy::default(), label: None })
+    }
+
+    /// 使用自定义的 reqwest 客户端和地址创建，例如用于连接模拟的 LCU
+    pub fn from_client(client: Client, url: impl Into<String>) -> Self {
+        LcuHttpClient { client, url: url.into(), retry_policy: RetryPolicy::default(), label: None }
+    }
+
+    /// 设置默认的重试策略
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// 设置日志中的标签
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// GET 请求，响应体反序列化为 T
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, LcuError> {
+        self.request(Method::GET, path, &()).await
+    }
+
+    /// POST 请求，body 为 () 时发送空请求体
+    pub async fn post<B: Serialize + ?Sized, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T, LcuError> {
+        self.request(Method::POST, path, body).await
+    }
+
+    /// PUT 请求，body 为 () 时发送空请求体
+    pub async fn put<B: Serialize + ?Sized, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T, LcuError> {
+        self.request(Method::PUT, path, body).await
+    }
+
+    /// PATCH 请求，body 为 () 时发送空请求体
+    pub async fn patch<B: Serialize + ?Sized, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T, LcuError> {
+        self.request(Method::PATCH, path, body).await
+    }
+
+    /// DELETE 请求
+    pub async fn delete<T: DeserializeOwned>(&self, path: &str) -> Result<T, LcuError> {
+        self.request(Method::DELETE, path, &()).await
+    }
+
+    /// 使用默认的重试策略发送请求
+    pub async fn request<B: Serialize + ?Sized, T: DeserializeOwned>(&self, method: Method, path: &str, body: &B) -> Result<T, LcuError> {
+        self.request_with_policy(method, path, body, &self.retry_policy).await
+    }
+
+    /// 使用指定的重试策略发送请求
+    pub async fn request_with_policy<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: &B,
+        retry_policy: &RetryPolicy,
+    ) -> Result<T, LcuError> {
+        let body = serde_json::to_value(body)?;
+        let mut attempt = 1;
+        loop {
+            match self.send(method.clone(), path, &body).await {
+                Ok(data) => return Ok(data),
+                Err(e) if retry_policy.should_retry(attempt, &e) => {
+                    let backoff = retry_policy.backoff(attempt);
+                    println!("{} {}请求 {} 失败: {}，{}毫秒后第{}次重试", get_now_str(), label_prefix(self.label.as_deref()), path, e, backoff.as_millis(), attempt);
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// 发送请求并检查状态码，非 2xx 时解析 LCU 的错误响应体，
+    /// 响应体为空时（例如 204）按 null 反序列化，因此 T 可以为 () 或 Option
+    async fn send<T: DeserializeOwned>(&self, method: Method, path: &str, body: &serde_json::Value) -> Result<T, LcuError> {
+        let mut request = self.client.request(method, format!("{}{}", self.url, path));
+        request = if body.is_null() { request.body("") } else { request.json(body) };
+        let response = request.send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(LcuError::from_response(status.as_u16(), &text));
+        }
+        let text = if text.trim().is_empty() { "null" } else { text.as_str() };
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// 获取当前登录的召唤师名称，优先使用 Riot ID
+    pub(in crate::lcu) async fn get_summoner_name(&self) -> Option<String> {
+        let summoner: serde_json::Value = self.get(lcu_api::CURRENT_SUMMONER).await.ok()?;
+        match (summoner["gameName"].as_str(), summoner["tagLine"].as_str()) {
+            (Some(name), Some(tag)) if !name.is_empty() => Some(format!("{}#{}", name, tag)),
+            _ => summoner["displayName"].as_str().filter(|name| !name.is_empty()).map(String::from),
+        }
+    }
+}
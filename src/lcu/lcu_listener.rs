@@ -1,113 +1,449 @@
-use super::{constants::{self, Value as ConstantValue}, utils::{gen_lcu_auth, get_lol_client_connect_info, get_now_str}};
+use super::error::LcuError;
+use super::event::LcuEventType;
+use super::tls::TlsMode;
+use super::{constants::{self, Value as ConstantValue}, utils::{gen_lcu_auth, get_now_str, label_prefix, LolClientConnectInfo}};
 use futures::SinkExt;
 use futures_util::StreamExt;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::Notify;
-use tokio::{
-    net::TcpStream,
-    sync::{broadcast, RwLock},
-};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::sync::{broadcast, RwLock};
 use tokio_tungstenite::Connector::NativeTls;
-use tokio_tungstenite::{
-    tungstenite::{protocol::WebSocketConfig, ClientRequestBuilder, Message},
-    MaybeTlsStream, WebSocketStream,
-};
+use tokio_tungstenite::tungstenite::{self, protocol::WebSocketConfig, ClientRequestBuilder, Message};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LcuData {
     pub data: serde_json::Value,
-    pub event_type: String,
+    pub event_type: LcuEventType,
     pub uri: String,
 }
 
+/// 发送给写任务的命令
+pub enum WsCommand {
+    /// 发送消息，发送结果通过 oneshot 返回
+    Send(Message, oneshot::Sender<Result<(), tungstenite::Error>>),
+    /// 发送关闭帧并结束写任务
+    Close,
+}
+
+/// websocket 的发送端，写任务独占连接的写入端，其他组件通过命令通道发送消息
+#[derive(Clone)]
+pub struct LcuWebsocketSender {
+    commands: mpsc::Sender<WsCommand>,
+}
+
+impl LcuWebsocketSender {
+    /// 发送消息，等待写入完成
+    pub async fn send(&self, message: Message) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (tx, rx) = oneshot::channel();
+        self.commands
+            .send(WsCommand::Send(message, tx))
+            .await
+            .map_err(|_| "websocket is closed")?;
+        rx.await.map_err(|_| "websocket is closed")??;
+        Ok(())
+    }
+
+    /// 关闭连接
+    pub async fn close(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.commands
+            .send(WsCommand::Close)
+            .await
+            .map_err(|_| "websocket is closed")?;
+        Ok(())
+    }
+
+    /// 写任务是否已经结束
+    pub fn is_closed(&self) -> bool {
+        self.commands.is_closed()
+    }
+}
+
+/// websocket 关闭的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum CloseReason {
+    /// 收到了关闭帧，没有携带状态码时为 1005
+    Closed { code: u16, reason: String },
+    /// 连接出错
+    Error(String),
+    /// 连接断开，但没有收到关闭帧
+    Eof,
+}
+
+impl Display for CloseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseReason::Closed { code, reason } => write!(f, "连接被关闭({}) {}", code, reason),
+            CloseReason::Error(e) => write!(f, "连接错误: {}", e),
+            CloseReason::Eof => write!(f, "连接意外断开"),
+        }
+    }
+}
+
+/// websocket 收到的消息统计
+#[derive(Debug, Default)]
+pub struct LcuWebsocketStats {
+    /// 文本消息
+    pub messages: AtomicU64,
+    /// 忽略的二进制消息
+    pub binary_frames: AtomicU64,
+    /// 收到的 ping
+    pub pings: AtomicU64,
+    /// 不是合法 JSON 的消息
+    pub invalid_json: AtomicU64,
+    /// 不是 WAMP 消息，例如不是数组或缺少消息类型
+    pub invalid_message: AtomicU64,
+    /// 事件内容无法解析为 LcuData
+    pub invalid_event: AtomicU64,
+    /// 未知的消息类型
+    pub unknown_operator: AtomicU64,
+}
+
+/// 等待 CallResult/CallError 的调用
+type PendingCalls = Arc<Mutex<HashMap<String, oneshot::Sender<Result<serde_json::Value, LcuError>>>>>;
+
 pub struct LcuWebsocket {
     pub data: Arc<RwLock<broadcast::Sender<LcuData>>>,
-    pub socket: Arc<RwLock<WebSocketStream<MaybeTlsStream<TcpStream>>>>,
-    pub stop_notify: Arc<Notify>,
+    pub sender: LcuWebsocketSender,
+    pub connect_info: LolClientConnectInfo,
+    /// 日志中区分多个客户端的标签，通常为召唤师名称
+    pub label: Option<String>,
+    /// 已订阅的事件主题及其引用计数
+    subscriptions: Arc<Mutex<HashMap<String, usize>>>,
+    pending_calls: PendingCalls,
+    next_call_id: AtomicU64,
+    /// 收到的消息统计
+    pub stats: Arc<LcuWebsocketStats>,
+    /// 连接关闭后记录关闭原因
+    close_reason: Arc<Mutex<Option<CloseReason>>>,
+    /// 读任务结束时被取消，此时 close_reason 已经写入
+    closed: CancellationToken,
 }
 impl LcuWebsocket {
-    pub async fn new(stop_notify: Arc<Notify>) -> Result<Self, Box<dyn Error + Send + Sync>> {
-        let connect_info = match get_lol_client_connect_info() {
-            Ok(info) => info,
-            Err(e) => return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, e.to_string())))
-        };
-        // allow invalid ssl certificates
-        let connector = native_tls::TlsConnector::builder()
-            .danger_accept_invalid_certs(true)
-            .danger_accept_invalid_hostnames(true)
-            .build()?;
+    pub async fn new(connect_info: LolClientConnectInfo, tls_mode: TlsMode, label: Option<String>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        // 默认只信任 Riot Games 根证书签发的证书
+        let connector = tls_mode.websocket_connector()?;
         // connect to the local websocket
-        let url = format!("wss://127.0.0.1:{}/", connect_info.port.clone()).parse()?;
+        let url = format!("wss://{}:{}/", connect_info.host(), connect_info.port).parse()?;
         let auth = gen_lcu_auth("riot", &connect_info.token.clone());
         let request =
             ClientRequestBuilder::new(url).with_header("authorization", auth);
-        let (mut socket, _) = tokio_tungstenite::connect_async_tls_with_config(
+        let (socket, _) = tokio_tungstenite::connect_async_tls_with_config(
             request,
             Some(WebSocketConfig::default()),
             false,
             Some(NativeTls(connector)),
         )
             .await?;
-        // subscribe to all events
-        let message = format!(
-            r#"[{}, "{}"]"#,
-            constants::Operator::Sub.value(),
-            constants::Event::OnJsonApiEvent.value()
-        );
-
-        socket
-            .send(Message::Text(message.to_owned()))
-            .await?;
+        // 读写分离，写入端交给写任务，其他组件通过命令通道发送订阅等消息，具体订阅哪些事件由 subscribe 决定
+        let (mut sink, mut stream) = socket.split();
+        let (command_tx, mut command_rx) = mpsc::channel::<WsCommand>(32);
+        tokio::spawn(async move {
+            while let Some(command) = command_rx.recv().await {
+                match command {
+                    WsCommand::Send(message, result) => {
+                        let _ = result.send(sink.send(message).await);
+                    }
+                    WsCommand::Close => {
+                        let _ = sink.close().await;
+                        break;
+                    }
+                }
+            }
+        });
         let (tx, _) = broadcast::channel(100);
         let lcu_listener = LcuWebsocket {
             data: Arc::new(RwLock::new(tx)),
-            socket: Arc::new(RwLock::new(socket)),
-            stop_notify: stop_notify.clone(),
+            sender: LcuWebsocketSender { commands: command_tx },
+            connect_info,
+            label,
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            pending_calls: Arc::new(Mutex::new(HashMap::new())),
+            next_call_id: AtomicU64::new(1),
+            stats: Arc::new(LcuWebsocketStats::default()),
+            close_reason: Arc::new(Mutex::new(None)),
+            closed: CancellationToken::new(),
         };
 
-        let c_socket = lcu_listener.socket.clone();
         let c_data = lcu_listener.data.clone();
-        let c_notify = lcu_listener.stop_notify.clone();
+        let c_sender = lcu_listener.sender.clone();
+        let c_pending_calls = lcu_listener.pending_calls.clone();
+        let c_stats = lcu_listener.stats.clone();
+        let c_close_reason = lcu_listener.close_reason.clone();
+        let c_closed = lcu_listener.closed.clone();
+        let prefix = label_prefix(lcu_listener.label.as_deref());
 
         tokio::spawn(async move {
-            let mut socket = c_socket.write().await;
             let broadcast = c_data.read().await;
-            while let Some(msg_result) = socket.next().await {
+            let mut close_reason = CloseReason::Eof;
+            while let Some(msg_result) = stream.next().await {
                 match msg_result {
-                    Ok(msg) => {
-                        if msg.is_empty() { continue; }
-                        let data: Result<(i32, String, Option<LcuData>), _> =
-                            serde_json::from_str(&msg.to_string());
-                        
-                        match data {
-                            Ok(parsed_data) => {
-                                if parsed_data.2.is_some() {
-                                    let lcu_data = parsed_data.2.unwrap().clone();
-                                    let _ = broadcast.send(lcu_data);
-                                }
-                            }
+                    Ok(Message::Text(text)) => {
+                        c_stats.messages.fetch_add(1, Ordering::Relaxed);
+                        if text.is_empty() { continue; }
+                        match serde_json::from_str::<Vec<serde_json::Value>>(&text) {
+                            Ok(message) => Self::dispatch(message, &broadcast, &c_pending_calls, &c_stats, &prefix).await,
                             Err(e) => {
-                                println!("{} 解析消息失败: {}", get_now_str(), e);
-                                continue;
+                                let count = c_stats.invalid_json.fetch_add(1, Ordering::Relaxed) + 1;
+                                println!("{} {}解析消息失败({}次): {}", get_now_str(), prefix, count, e);
                             }
                         }
                     }
+                    Ok(Message::Ping(_)) => {
+                        // tungstenite 会自动回复 pong，并在下次读取时发送，这里只做统计
+                        c_stats.pings.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(Message::Pong(_)) | Ok(Message::Frame(_)) => {}
+                    Ok(Message::Binary(_)) => {
+                        // LCU 只发送文本消息
+                        c_stats.binary_frames.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(Message::Close(frame)) => {
+                        close_reason = match frame {
+                            Some(frame) => CloseReason::Closed { code: frame.code.into(), reason: frame.reason.to_string() },
+                            None => CloseReason::Closed { code: 1005, reason: String::new() },
+                        };
+                        break;
+                    }
                     Err(e) => {
-                        println!("{} WebSocket连接错误: {}，连接将关闭", get_now_str(), e);
-                        c_notify.notify_one();
+                        println!("{} {}WebSocket连接错误: {}，连接将关闭", get_now_str(), prefix, e);
+                        close_reason = CloseReason::Error(e.to_string());
                         break;
                     }
                 }
             }
-            // 连接已关闭，通知监听器
-            println!("{} WebSocket连接已关闭", get_now_str());
-            c_notify.notify_one();
+            // 连接已关闭，结束写任务，未返回的调用全部失败，并通知监听器
+            let _ = c_sender.commands.try_send(WsCommand::Close);
+            for (_, pending) in c_pending_calls.lock().await.drain() {
+                let _ = pending.send(Err(LcuError::Disconnected));
+            }
+            println!("{} {}WebSocket连接已关闭: {}", get_now_str(), prefix, close_reason);
+            *c_close_reason.lock().await = Some(close_reason);
+            c_closed.cancel();
         });
         Ok(lcu_listener)
     }
+
+    /// 按 WAMP 消息类型分发收到的消息
+    async fn dispatch(
+        message: Vec<serde_json::Value>,
+        broadcast: &broadcast::Sender<LcuData>,
+        pending_calls: &PendingCalls,
+        stats: &LcuWebsocketStats,
+        prefix: &str,
+    ) {
+        let Some(operator) = message.first().and_then(|op| op.as_i64()) else {
+            let count = stats.invalid_message.fetch_add(1, Ordering::Relaxed) + 1;
+            println!("{} {}解析消息失败({}次): 缺少消息类型", get_now_str(), prefix, count);
+            return;
+        };
+        match constants::Operator::from_value(operator as i32) {
+            // [8, topic, event]
+            constants::Operator::Event => {
+                let Some(event) = message.get(2) else {
+                    let count = stats.invalid_event.fetch_add(1, Ordering::Relaxed) + 1;
+                    println!("{} {}解析事件失败({}次): 缺少事件内容", get_now_str(), prefix, count);
+                    return;
+                };
+                match serde_json::from_value::<LcuData>(event.clone()) {
+                    Ok(lcu_data) => {
+                        let _ = broadcast.send(lcu_data);
+                    }
+                    Err(e) => {
+                        let count = stats.invalid_event.fetch_add(1, Ordering::Relaxed) + 1;
+                        println!("{} {}解析事件失败({}次): {}", get_now_str(), prefix, count, e);
+                    }
+                }
+            }
+            // [3, callId, result]
+            constants::Operator::CallResult => {
+                let call_id = message.get(1).and_then(|id| id.as_str()).unwrap_or_default();
+                if let Some(pending) = pending_calls.lock().await.remove(call_id) {
+                    let _ = pending.send(Ok(message.get(2).cloned().unwrap_or_default()));
+                }
+            }
+            // [4, callId, errorUri, errorDesc, errorDetails]
+            constants::Operator::CallError => {
+                let call_id = message.get(1).and_then(|id| id.as_str()).unwrap_or_default();
+                if let Some(pending) = pending_calls.lock().await.remove(call_id) {
+                    let _ = pending.send(Err(LcuError::Call {
+                        error_uri: message.get(2).and_then(|uri| uri.as_str()).unwrap_or_default().to_string(),
+                        description: message.get(3).and_then(|desc| desc.as_str()).unwrap_or_default().to_string(),
+                        details: message.get(4).cloned(),
+                    }));
+                }
+            }
+            // [0, sessionId, protocolVersion, serverIdent]
+            constants::Operator::Welcome => {
+                println!("{} {}WebSocket会话已建立: {}", get_now_str(), prefix, message.get(1).cloned().unwrap_or_default());
+            }
+            constants::Operator::Unknown(_) => {
+                stats.unknown_operator.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    /// 通过已打开的 websocket 调用 LCU 的过程，返回 CallResult 的结果，CallError 时返回 `LcuError::Call`
+    ///
+    /// 调用不会超时，需要时请在外层使用 `tokio::time::timeout`
+    pub async fn call(&self, procedure: &str, args: Vec<serde_json::Value>) -> Result<serde_json::Value, LcuError> {
+        let call_id = self.next_call_id.fetch_add(1, Ordering::Relaxed).to_string();
+        let (tx, rx) = oneshot::channel();
+        self.pending_calls.lock().await.insert(call_id.clone(), tx);
+
+        // [2, callId, procUri, args...]
+        let mut message = vec![
+            serde_json::json!(constants::Operator::Call.value()),
+            serde_json::json!(call_id),
+            serde_json::json!(procedure),
+        ];
+        message.extend(args);
+        let message = serde_json::Value::Array(message).to_string();
+        if self.sender.send(Message::Text(message)).await.is_err() {
+            self.pending_calls.lock().await.remove(&call_id);
+            return Err(LcuError::Disconnected);
+        }
+        rx.await.unwrap_or(Err(LcuError::Disconnected))
+    }
+
+    /// 订阅事件主题，同一主题多次订阅只发送一次订阅消息
+    ///
+    /// 主题可以是 `OnJsonApiEvent` 订阅所有事件，也可以通过 `Event::topic` 只订阅指定接口
+    pub async fn subscribe(&self, topic: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        let count = subscriptions.entry(topic.to_string()).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            if let Err(e) = self.send_operator(constants::Operator::Sub, topic).await {
+                subscriptions.remove(topic);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// 取消订阅事件主题，引用计数归零时才发送取消订阅消息
+    pub async fn unsubscribe(&self, topic: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        let Some(count) = subscriptions.get_mut(topic) else {
+            return Ok(());
+        };
+        *count -= 1;
+        if *count == 0 {
+            subscriptions.remove(topic);
+            self.send_operator(constants::Operator::DisSub, topic).await?;
+        }
+        Ok(())
+    }
+
+    /// 连接关闭的原因，连接未关闭时为 None
+    pub async fn close_reason(&self) -> Option<CloseReason> {
+        self.close_reason.lock().await.clone()
+    }
+
+    /// 连接关闭时被取消的令牌，可以在 select 中等待连接关闭
+    pub fn closed(&self) -> CancellationToken {
+        self.closed.clone()
+    }
+
+    /// 获取发送端，可以交给其他组件在读取事件的同时发送消息
+    pub fn sender(&self) -> LcuWebsocketSender {
+        self.sender.clone()
+    }
+
+    /// 当前已订阅的事件主题
+    pub async fn subscriptions(&self) -> Vec<String> {
+        self.subscriptions.lock().await.keys().cloned().collect()
+    }
+
+    async fn send_operator(&self, operator: constants::Operator, topic: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let message = serde_json::json!([operator.value(), topic]).to_string();
+        self.sender.send(Message::Text(message)).await
+    }
+}
+
+impl Drop for LcuWebsocket {
+    fn drop(&mut self) {
+        // 重连时旧连接被替换，主动关闭，读任务随之结束
+        let _ = self.sender.commands.try_send(WsCommand::Close);
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(value: serde_json::Value) -> Vec<serde_json::Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    async fn dispatch(value: serde_json::Value, pending_calls: &PendingCalls, stats: &LcuWebsocketStats) -> broadcast::Receiver<LcuData> {
+        let (tx, rx) = broadcast::channel(4);
+        LcuWebsocket::dispatch(message(value), &tx, pending_calls, stats, "").await;
+        rx
+    }
+
+    async fn pending(pending_calls: &PendingCalls, call_id: &str) -> oneshot::Receiver<Result<serde_json::Value, LcuError>> {
+        let (tx, rx) = oneshot::channel();
+        pending_calls.lock().await.insert(call_id.to_string(), tx);
+        rx
+    }
+
+    #[tokio::test]
+    async fn call_result_resolves_pending_call() {
+        let pending_calls = PendingCalls::default();
+        let stats = LcuWebsocketStats::default();
+        let rx = pending(&pending_calls, "1").await;
+        dispatch(json!([3, "1", {"ok": true}]), &pending_calls, &stats).await;
+        assert_eq!(rx.await.unwrap().unwrap(), json!({"ok": true}));
+        assert!(pending_calls.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn call_error_resolves_pending_call() {
+        let pending_calls = PendingCalls::default();
+        let stats = LcuWebsocketStats::default();
+        let rx = pending(&pending_calls, "2").await;
+        dispatch(json!([4, "2", "error:uri", "something failed", {"code": 1}]), &pending_calls, &stats).await;
+        match rx.await.unwrap() {
+            Err(LcuError::Call { error_uri, description, details }) => {
+                assert_eq!(error_uri, "error:uri");
+                assert_eq!(description, "something failed");
+                assert_eq!(details, Some(json!({"code": 1})));
+            }
+            other => panic!("expected call error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_call_id_is_ignored() {
+        let pending_calls = PendingCalls::default();
+        let stats = LcuWebsocketStats::default();
+        let mut rx = pending(&pending_calls, "1").await;
+        dispatch(json!([3, "99", null]), &pending_calls, &stats).await;
+        dispatch(json!([4, "99", "error:uri", "desc"]), &pending_calls, &stats).await;
+        dispatch(json!([3]), &pending_calls, &stats).await;
+        // 其他调用不受影响
+        assert!(rx.try_recv().is_err());
+        assert_eq!(pending_calls.lock().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn event_is_broadcast_and_missing_payload_is_counted() {
+        let pending_calls = PendingCalls::default();
+        let stats = LcuWebsocketStats::default();
+        let event = json!({"data": "ReadyCheck", "eventType": "Update", "uri": "/lol-gameflow/v1/gameflow-phase"});
+        let mut rx = dispatch(json!([8, "OnJsonApiEvent", event]), &pending_calls, &stats).await;
+        assert_eq!(rx.try_recv().unwrap().uri, "/lol-gameflow/v1/gameflow-phase");
+        dispatch(json!([8, "OnJsonApiEvent"]), &pending_calls, &stats).await;
+        assert_eq!(stats.invalid_event.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::lcu::utils::{get_now_str, LolClientConnectInfo};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::sleep;
+
+/// 手动指定客户端安装目录的环境变量
+pub const INSTALL_DIR_ENV: &str = "PORO_LOL_INSTALL_DIR";
+
+/// 客户端启动后写入安装目录的文件名
+const LOCKFILE_NAME: &str = "lockfile";
+
+/// 轮询 lockfile 的间隔
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 常见的默认安装目录
+const DEFAULT_INSTALL_DIRS: &[&str] = &[
+    r"C:\Riot Games\League of Legends",
+    r"D:\Riot Games\League of Legends",
+    "/Applications/League of Legends.app/Contents/LoL",
+];
+
+/// 查找客户端安装目录，优先使用环境变量，其次尝试默认安装目录
+pub fn find_install_dir() -> Option<PathBuf> {
+    if let Ok(dir) = std::env::var(INSTALL_DIR_ENV) {
+        return Some(PathBuf::from(dir));
+    }
+    let mut candidates: Vec<PathBuf> = DEFAULT_INSTALL_DIRS.iter().map(PathBuf::from).collect();
+    // Lutris 默认的 Wine 前缀
+    if let Ok(home) = std::env::var("HOME") {
+        candidates.push(Path::new(&home).join("Games/league-of-legends/drive_c/Riot Games/League of Legends"));
+    }
+    candidates.into_iter().find(|dir| dir.is_dir())
+}
+
+/// 查找 lockfile 路径，找不到安装目录时返回 None
+pub fn find_lockfile() -> Option<PathBuf> {
+    find_install_dir().map(|dir| dir.join(LOCKFILE_NAME))
+}
+
+/// 读取并解析 lockfile，格式为 `name:pid:port:password:protocol`
+pub fn read_lockfile(path: &Path) -> Result<LolClientConnectInfo, Box<dyn Error>> {
+    let mut info = parse_lockfile(&fs::read_to_string(path)?)?;
+    info.install_directory = path.parent().map(Path::to_path_buf);
+    Ok(info)
+}
+
+fn parse_lockfile(content: &str) -> Result<LolClientConnectInfo, Box<dyn Error>> {
+    let parts: Vec<&str> = content.trim().split(':').collect();
+    if parts.len() != 5 {
+        return Err(From::from(format!("Invalid lockfile: {}", content)));
+    }
+    let port = parts[2].parse::<i32>()?;
+    let token = parts[3].to_string();
+    if port == 0 || token.is_empty() {
+        return Err(From::from("Couldn't get lol client connect"));
+    }
+    Ok(LolClientConnectInfo {
+        port,
+        token,
+        pid: parts[1].parse::<u32>().ok(),
+        ..Default::default()
+    })
+}
+
+/// 监听 lockfile 的变化，客户端重启重写 lockfile 后立即推送新的连接信息
+///
+/// 客户端退出时 lockfile 会被删除，此时推送 None
+pub fn watch_lockfile(path: PathBuf) -> watch::Receiver<Option<LolClientConnectInfo>> {
+    let (tx, rx) = watch::channel(read_lockfile(&path).ok());
+    tokio::spawn(async move {
+        loop {
+            sleep(WATCH_INTERVAL).await;
+            let info = read_lockfile(&path).ok();
+            let changed = tx.send_if_modified(|current| {
+                if *current != info {
+                    *current = info;
+                    return true;
+                }
+                false
+            });
+            if changed {
+                println!("{} 检测到客户端 lockfile 发生变化", get_now_str());
+            }
+            // 所有接收端都已关闭
+            if tx.is_closed() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// 等待 lockfile 写入新的连接信息，超时返回 None
+pub async fn wait_for_new_connect_info(
+    rx: &mut watch::Receiver<Option<LolClientConnectInfo>>,
+    timeout: Duration,
+) -> Option<LolClientConnectInfo> {
+    tokio::time::timeout(timeout, async {
+        loop {
+            rx.changed().await.ok()?;
+            if let Some(info) = rx.borrow_and_update().clone() {
+                return Some(info);
+            }
+        }
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_valid_lockfile() {
+        let info = parse_lockfile("LeagueClient:12345:52437:abc-DEF_123:https\n").unwrap();
+        assert_eq!(info.pid, Some(12345));
+        assert_eq!(info.port, 52437);
+        assert_eq!(info.token, "abc-DEF_123");
+        assert_eq!(info.install_directory, None);
+    }
+
+    #[test]
+    fn parse_rejects_wrong_field_count() {
+        assert!(parse_lockfile("LeagueClient:12345:52437:abc").is_err());
+        assert!(parse_lockfile("LeagueClient:12345:52437:abc:https:extra").is_err());
+        assert!(parse_lockfile("").is_err());
+    }
+
+    #[test]
+    fn parse_rejects_non_numeric_port() {
+        assert!(parse_lockfile("LeagueClient:12345:port:abc:https").is_err());
+    }
+
+    #[test]
+    fn parse_rejects_empty_password() {
+        assert!(parse_lockfile("LeagueClient:12345:52437::https").is_err());
+    }
+
+    #[test]
+    fn read_lockfile_sets_install_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCKFILE_NAME);
+        fs::write(&path, "LeagueClient:1:52437:abc:https").unwrap();
+        let info = read_lockfile(&path).unwrap();
+        assert_eq!(info.install_directory.as_deref(), Some(dir.path()));
+    }
+}
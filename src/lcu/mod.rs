@@ -7,4 +7,28 @@ pub mod lcu_client;
 #[allow(unused)]
 pub mod utils;
 #[allow(unused)]
-pub mod lcu_client_util;
\ No newline at end of file
+pub mod lcu_client_util;
+#[allow(unused)]
+pub mod lockfile;
+#[allow(unused)]
+pub mod process_inspector;
+#[allow(unused)]
+pub mod connect_source;
+#[allow(unused)]
+pub mod lcu_http_client;
+#[allow(unused)]
+pub mod error;
+#[allow(unused)]
+pub mod retry;
+#[allow(unused)]
+pub mod tls;
+#[allow(unused)]
+pub mod event;
+#[allow(unused)]
+pub mod models;
+#[allow(unused)]
+pub mod router;
+#[allow(unused)]
+pub mod handler;
+#[allow(unused)]
+pub mod client_registry;
\ No newline at end of file
@@ -0,0 +1,13 @@
+use crate::lcu::constants::GameState;
+use serde_derive::{Deserialize, Serialize};
+
+/// 对局流程会话，对应 /lol-gameflow/v1/session，只列出常用字段
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GameflowSession {
+    /// 当前游戏状态
+    pub phase: GameState,
+    pub game_data: serde_json::Value,
+    pub game_client: serde_json::Value,
+    pub map: serde_json::Value,
+}
@@ -0,0 +1,281 @@
+use crate::lcu::utils::get_now_str;
+use encoding::all::GBK;
+use encoding::{DecoderTrap, Encoding};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// 客户端进程名
+pub const LOL_CLIENT_PROCESS: &str = "LeagueClientUx.exe";
+
+/// 配置进程查询方式及顺序的环境变量，例如 `PORO_PROCESS_INSPECTORS=powershell,wmic`
+pub const PROCESS_INSPECTORS_ENV: &str = "PORO_PROCESS_INSPECTORS";
+
+/// 查询客户端进程启动命令的方式
+pub trait ProcessInspector: Send + Sync {
+    /// 名称，用于日志和配置
+    fn name(&self) -> &'static str;
+
+    /// 返回所有客户端进程的启动命令
+    fn command_lines(&self) -> Result<Vec<String>, Box<dyn Error>>;
+}
+
+/// 通过 wmic 查询，新版 Windows 已不再默认安装 wmic
+pub struct WmicInspector;
+
+impl ProcessInspector for WmicInspector {
+    fn name(&self) -> &'static str {
+        "wmic"
+    }
+
+    fn command_lines(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let output = run_command(
+            "wmic",
+            &["PROCESS", "WHERE", "name='LeagueClientUx.exe'", "GET", "commandline"],
+        )?;
+        // 第一行为表头 CommandLine
+        Ok(filter_client_lines(&output))
+    }
+}
+
+/// 通过 PowerShell 的 Get-CimInstance 查询
+pub struct PowerShellInspector;
+
+impl ProcessInspector for PowerShellInspector {
+    fn name(&self) -> &'static str {
+        "powershell"
+    }
+
+    fn command_lines(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let script = "[Console]::OutputEncoding = [Text.Encoding]::UTF8; \
+            Get-CimInstance -ClassName Win32_Process -Filter 'name=''LeagueClientUx.exe''' \
+            | Select-Object -ExpandProperty CommandLine";
+        let output = run_command("powershell", &["-NoProfile", "-NonInteractive", "-Command", script])?;
+        Ok(filter_client_lines(&output))
+    }
+}
+
+/// 扫描 proc 目录下所有进程的 cmdline，适用于 Linux 下通过 Wine/Lutris 运行的客户端
+pub struct ProcInspector {
+    /// 通常为 `/proc`，测试时可以传入伪造的目录
+    pub root: PathBuf,
+}
+
+impl Default for ProcInspector {
+    fn default() -> Self {
+        ProcInspector { root: PathBuf::from("/proc") }
+    }
+}
+
+impl ProcessInspector for ProcInspector {
+    fn name(&self) -> &'static str {
+        "proc"
+    }
+
+    fn command_lines(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut lines = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            // 只关心以 pid 命名的目录
+            if !entry.file_name().to_string_lossy().chars().all(|c| c.is_ascii_digit()) {
+                continue;
+            }
+            // 进程可能在扫描期间退出，或者没有读取权限
+            let cmdline = match fs::read(entry.path().join("cmdline")) {
+                Ok(cmdline) => cmdline,
+                Err(_) => continue,
+            };
+            // cmdline 中的参数以 \0 分隔
+            let args: Vec<String> = cmdline
+                .split(|b| *b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect();
+            // Wine 下 argv[0] 为 Windows 风格路径，例如 C:\Riot Games\League of Legends\LeagueClientUx.exe
+            let is_lol_client = args
+                .first()
+                .is_some_and(|exe| exe.rsplit(['/', '\\']).next() == Some(LOL_CLIENT_PROCESS));
+            if is_lol_client {
+                lines.push(join_args(&args));
+            }
+        }
+        Ok(lines)
+    }
+}
+
+/// 解析 ps 的输出，适用于 macOS 以及没有 /proc 的类 Unix 系统
+pub struct PsInspector;
+
+impl ProcessInspector for PsInspector {
+    fn name(&self) -> &'static str {
+        "ps"
+    }
+
+    fn command_lines(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let output = run_command("ps", &["-A", "-ww", "-o", "args="])?;
+        Ok(filter_client_lines(&output))
+    }
+}
+
+/// 返回固定的启动命令，用于测试或手动指定
+pub struct StaticInspector {
+    pub lines: Vec<String>,
+}
+
+impl ProcessInspector for StaticInspector {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    fn command_lines(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self.lines.clone())
+    }
+}
+
+/// 根据名称创建查询方式
+pub fn inspector_from_name(name: &str) -> Option<Box<dyn ProcessInspector>> {
+    match name.trim() {
+        "wmic" => Some(Box::new(WmicInspector)),
+        "powershell" => Some(Box::new(PowerShellInspector)),
+        "proc" => Some(Box::new(ProcInspector::default())),
+        "ps" => Some(Box::new(PsInspector)),
+        _ => None,
+    }
+}
+
+/// 获取配置的查询方式及顺序，未配置时使用当前平台的默认顺序
+pub fn configured_inspectors() -> Vec<Box<dyn ProcessInspector>> {
+    if let Ok(names) = std::env::var(PROCESS_INSPECTORS_ENV) {
+        let inspectors: Vec<Box<dyn ProcessInspector>> = names
+            .split(',')
+            .filter_map(|name| {
+                let inspector = inspector_from_name(name);
+                if inspector.is_none() {
+                    println!("{} 未知的进程查询方式: {}", get_now_str(), name);
+                }
+                inspector
+            })
+            .collect();
+        if !inspectors.is_empty() {
+            return inspectors;
+        }
+    }
+    default_inspectors()
+}
+
+/// 当前平台的默认查询顺序
+pub fn default_inspectors() -> Vec<Box<dyn ProcessInspector>> {
+    if cfg!(windows) {
+        vec![Box::new(WmicInspector), Box::new(PowerShellInspector)]
+    } else if cfg!(target_os = "linux") {
+        vec![Box::new(ProcInspector::default()), Box::new(PsInspector)]
+    } else {
+        vec![Box::new(PsInspector)]
+    }
+}
+
+fn run_command(program: &str, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new(program)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stdin(Stdio::null()) // 屏蔽 stdin，防止其他输入干扰
+        .stderr(Stdio::null()) // 屏蔽 stderr 输出
+        .output()?;
+    Ok(decode_output(&output.stdout))
+}
+
+/// 解码命令输出：优先按 UTF-8 解码，失败时再按 GBK 解码（中文 Windows 的默认代码页），
+/// 仍无法识别的字节会被替换，不影响端口和 token 的解析
+fn decode_output(buffer: &[u8]) -> String {
+    match std::str::from_utf8(buffer) {
+        Ok(output) => output.to_string(),
+        Err(_) => GBK
+            .decode(buffer, DecoderTrap::Replace)
+            .unwrap_or_else(|_| String::from_utf8_lossy(buffer).into_owned()),
+    }
+}
+
+/// 拼接参数，含空白的参数加上双引号，保证可以被重新拆分
+pub(in crate::lcu) fn join_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if arg.contains(char::is_whitespace) {
+                format!("\"{}\"", arg)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn filter_client_lines(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.contains(LOL_CLIENT_PROCESS))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn write_cmdline(root: &Path, dir: &str, args: &[&str]) {
+        let dir = root.join(dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut cmdline = args.join("\0").into_bytes();
+        cmdline.push(0);
+        fs::write(dir.join("cmdline"), cmdline).unwrap();
+    }
+
+    #[test]
+    fn proc_inspector_finds_wine_client() {
+        let root = tempfile::tempdir().unwrap();
+        write_cmdline(
+            root.path(),
+            "1234",
+            &[
+                r"C:\Riot Games\League of Legends\LeagueClientUx.exe",
+                "--app-port=52437",
+                "--remoting-auth-token=abc",
+                "--install-directory=C:/Riot Games/League of Legends",
+            ],
+        );
+        // 不是以 pid 命名的目录
+        write_cmdline(root.path(), "self", &["LeagueClientUx.exe", "--app-port=1"]);
+        // 无关的进程
+        write_cmdline(root.path(), "42", &["/usr/bin/wine", "LeagueClient.exe", "--app-port=2"]);
+
+        let inspector = ProcInspector { root: root.path().to_path_buf() };
+        let lines = inspector.command_lines().unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                "\"C:\\Riot Games\\League of Legends\\LeagueClientUx.exe\" --app-port=52437 --remoting-auth-token=abc \
+                 \"--install-directory=C:/Riot Games/League of Legends\""
+            ]
+        );
+    }
+
+    #[test]
+    fn proc_inspector_skips_missing_cmdline() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("7")).unwrap();
+        let inspector = ProcInspector { root: root.path().to_path_buf() };
+        assert!(inspector.command_lines().unwrap().is_empty());
+    }
+
+    #[test]
+    fn proc_inspector_fails_without_root() {
+        let root = tempfile::tempdir().unwrap();
+        let inspector = ProcInspector { root: root.path().join("missing") };
+        assert!(inspector.command_lines().is_err());
+    }
+}
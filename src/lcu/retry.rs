@@ -0,0 +1,133 @@
+use crate::lcu::error::LcuError;
+use rand::Rng;
+use std::time::Duration;
+
+/// LCU 请求的重试策略，客户端启动过程中接口可能返回 404/503 或拒绝连接
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// 最多请求次数，包括第一次请求，为 1 时不重试
+    pub max_attempts: u32,
+    /// 第一次重试前的等待时间，之后每次翻倍
+    pub initial_backoff: Duration,
+    /// 单次等待时间的上限
+    pub max_backoff: Duration,
+    /// 随机抖动比例，取值 0~1，等待时间会在 `backoff * (1 ± jitter)` 之间随机
+    pub jitter: f64,
+    /// 需要重试的状态码
+    pub retryable_statuses: Vec<u16>,
+    /// 连接失败或超时时是否重试
+    pub retry_on_connect_error: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::none()
+    }
+}
+
+impl RetryPolicy {
+    /// 不重试
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            jitter: 0.0,
+            retryable_statuses: Vec::new(),
+            retry_on_connect_error: false,
+        }
+    }
+
+    /// 用于接受对局等关键操作，在几百毫秒内快速重试
+    pub fn critical() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(400),
+            jitter: 0.2,
+            retryable_statuses: vec![404, 500, 502, 503, 504],
+            retry_on_connect_error: true,
+        }
+    }
+
+    /// 第 attempt 次请求失败后（从 1 开始）的等待时间
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let backoff = self.initial_backoff.saturating_mul(1 << exponent).min(self.max_backoff);
+        if self.jitter <= 0.0 || backoff.is_zero() {
+            return backoff;
+        }
+        let jitter = self.jitter.min(1.0);
+        backoff.mul_f64(rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter))
+    }
+
+    /// 第 attempt 次请求失败后是否需要重试
+    pub fn should_retry(&self, attempt: u32, error: &LcuError) -> bool {
+        if attempt >= self.max_attempts {
+            return false;
+        }
+        match error {
+            LcuError::Request(e) => self.retry_on_connect_error && (e.is_connect() || e.is_timeout()),
+            LcuError::Api { status, .. } => self.retryable_statuses.contains(status),
+            LcuError::Json(_) | LcuError::Call { .. } | LcuError::Disconnected => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error(status: u16) -> LcuError {
+        LcuError::Api { status, error_code: None, message: None }
+    }
+
+    fn without_jitter() -> RetryPolicy {
+        RetryPolicy { jitter: 0.0, ..RetryPolicy::critical() }
+    }
+
+    #[test]
+    fn backoff_doubles_until_capped() {
+        let policy = without_jitter();
+        let backoffs: Vec<u128> = (1..=6).map(|attempt| policy.backoff(attempt).as_millis()).collect();
+        assert_eq!(backoffs, vec![50, 100, 200, 400, 400, 400]);
+        // 极大的重试次数不会溢出
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn backoff_jitter_stays_in_range() {
+        let policy = RetryPolicy::critical();
+        for _ in 0..100 {
+            let backoff = policy.backoff(2).as_millis();
+            assert!((80..=120).contains(&backoff), "{}", backoff);
+        }
+    }
+
+    #[test]
+    fn should_retry_stops_at_max_attempts() {
+        let policy = RetryPolicy::critical();
+        assert!(policy.should_retry(1, &api_error(503)));
+        assert!(policy.should_retry(4, &api_error(503)));
+        assert!(!policy.should_retry(5, &api_error(503)));
+    }
+
+    #[test]
+    fn should_retry_only_retryable_statuses() {
+        let policy = RetryPolicy::critical();
+        for status in [404, 500, 502, 503, 504] {
+            assert!(policy.should_retry(1, &api_error(status)), "{}", status);
+        }
+        for status in [400, 401, 403, 409] {
+            assert!(!policy.should_retry(1, &api_error(status)), "{}", status);
+        }
+        assert!(!policy.should_retry(1, &LcuError::Disconnected));
+    }
+
+    #[test]
+    fn default_policy_never_retries() {
+        let policy = RetryPolicy::default();
+        assert!(!policy.should_retry(1, &api_error(503)));
+        assert_eq!(policy.backoff(1), Duration::ZERO);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::lcu::constants::{Event, Value};
+use crate::lcu::event::LcuEventType;
+use crate::lcu::lcu_listener::LcuData;
+use std::collections::HashMap;
+
+/// 从 URI 中提取的路径参数，末尾通配符匹配到的部分保存在 `*` 中
+pub type PathParams = HashMap<String, String>;
+
+/// URI 模式中的一段
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// 必须完全相同
+    Literal(String),
+    /// `{name}`，匹配任意一段并作为路径参数
+    Param(String),
+    /// `*`，匹配任意一段，位于末尾时匹配剩余的所有段
+    Wildcard,
+}
+
+/// URI 模式，例如 `/lol-champ-select/v1/summoners/{cellId}`、`/lol-chat/v1/conversations/*`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UriPattern {
+    pattern: String,
+    segments: Vec<Segment>,
+}
+
+impl UriPattern {
+    pub fn parse(pattern: &str) -> Self {
+        let segments = pattern
+            .trim_matches('/')
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                if segment == "*" {
+                    Segment::Wildcard
+                } else if let Some(name) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                    Segment::Param(name.to_string())
+                } else {
+                    Segment::Literal(segment.to_string())
+                }
+            })
+            .collect();
+        UriPattern { pattern: pattern.to_string(), segments }
+    }
+
+    /// 原始的模式字符串
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// 不含参数和通配符，即模式本身就是一个 URI
+    pub fn is_concrete(&self) -> bool {
+        self.segments.iter().all(|segment| matches!(segment, Segment::Literal(_)))
+    }
+
+    /// 匹配 URI，匹配成功时返回提取出的路径参数
+    pub fn matches(&self, uri: &str) -> Option<PathParams> {
+        let parts: Vec<&str> = uri.trim_matches('/').split('/').filter(|part| !part.is_empty()).collect();
+        let mut params = PathParams::new();
+        for (index, segment) in self.segments.iter().enumerate() {
+            let is_last = index == self.segments.len() - 1;
+            match segment {
+                Segment::Wildcard if is_last => {
+                    if parts.len() <= index {
+                        return None;
+                    }
+                    params.insert("*".to_string(), parts[index..].join("/"));
+                    return Some(params);
+                }
+                Segment::Wildcard => {
+                    parts.get(index)?;
+                }
+                Segment::Param(name) => {
+                    params.insert(name.clone(), parts.get(index)?.to_string());
+                }
+                Segment::Literal(literal) => {
+                    if parts.get(index) != Some(&literal.as_str()) {
+                        return None;
+                    }
+                }
+            }
+        }
+        (parts.len() == self.segments.len()).then_some(params)
+    }
+
+    /// 订阅用的事件主题，取第一个参数或通配符之前的部分，
+    /// 模式以参数或通配符开头时订阅所有事件
+    pub fn topic(&self) -> String {
+        let prefix: Vec<&str> = self
+            .segments
+            .iter()
+            .map_while(|segment| match segment {
+                Segment::Literal(literal) => Some(literal.as_str()),
+                _ => None,
+            })
+            .collect();
+        if prefix.is_empty() {
+            return Event::OnJsonApiEvent.value().to_string();
+        }
+        Event::OnJsonApiEvent.topic(&format!("/{}", prefix.join("/")))
+    }
+}
+
+/// 一条路由
+pub struct Route<H> {
+    pub pattern: UriPattern,
+    /// 只处理这些类型的事件，为空时处理所有类型
+    pub event_types: Vec<LcuEventType>,
+    pub handler: H,
+}
+
+/// 按 URI 模式和事件类型分发事件
+pub struct EventRouter<H> {
+    routes: Vec<Route<H>>,
+}
+
+impl<H> Default for EventRouter<H> {
+    fn default() -> Self {
+        EventRouter { routes: Vec::new() }
+    }
+}
+
+impl<H> EventRouter<H> {
+    /// 添加路由，event_types 为空时处理所有类型的事件
+    pub fn add(&mut self, pattern: &str, event_types: &[LcuEventType], handler: H) {
+        self.routes.push(Route {
+            pattern: UriPattern::parse(pattern),
+            event_types: event_types.to_vec(),
+            handler,
+        });
+    }
+
+    /// 找出所有匹配事件的路由及提取出的路径参数，按添加顺序返回
+    pub fn route(&self, lcu_data: &LcuData) -> Vec<(&H, PathParams)> {
+        self.routes
+            .iter()
+            .filter(|route| route.event_types.is_empty() || route.event_types.contains(&lcu_data.event_type))
+            .filter_map(|route| route.pattern.matches(&lcu_data.uri).map(|params| (&route.handler, params)))
+            .collect()
+    }
+
+    /// 所有路由，按添加顺序排列
+    pub fn routes(&self) -> &[Route<H>] {
+        &self.routes
+    }
+
+    pub fn routes_mut(&mut self) -> impl Iterator<Item=&mut Route<H>> {
+        self.routes.iter_mut()
+    }
+
+    /// 移除处理函数满足条件的路由，返回被移除的路由
+    pub fn remove_where(&mut self, mut predicate: impl FnMut(&H) -> bool) -> Vec<Route<H>> {
+        let (removed, kept) = std::mem::take(&mut self.routes).into_iter().partition(|route| predicate(&route.handler));
+        self.routes = kept;
+        removed
+    }
+
+    /// 所有路由需要订阅的事件主题，每条路由对应一个引用计数
+    pub fn topics(&self) -> Vec<String> {
+        self.routes.iter().map(|route| route.pattern.topic()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lcu_data(uri: &str, event_type: LcuEventType) -> LcuData {
+        LcuData { data: serde_json::Value::Null, event_type, uri: uri.to_string() }
+    }
+
+    #[test]
+    fn matches_extracts_params() {
+        let pattern = UriPattern::parse("/lol-champ-select/v1/summoners/{cellId}");
+        let params = pattern.matches("/lol-champ-select/v1/summoners/3").unwrap();
+        assert_eq!(params.get("cellId").map(String::as_str), Some("3"));
+        assert!(pattern.matches("/lol-champ-select/v1/session").is_none());
+    }
+
+    #[test]
+    fn matches_requires_same_length() {
+        let pattern = UriPattern::parse("/lol-champ-select/v1/summoners/{cellId}");
+        assert!(pattern.matches("/lol-champ-select/v1/summoners").is_none());
+        assert!(pattern.matches("/lol-champ-select/v1/summoners/3/extra").is_none());
+        assert!(UriPattern::parse("/lol-gameflow/v1/gameflow-phase").matches("/lol-gameflow/v1/gameflow-phase").is_some());
+    }
+
+    #[test]
+    fn trailing_wildcard_matches_rest() {
+        let pattern = UriPattern::parse("/lol-chat/v1/conversations/*");
+        let params = pattern.matches("/lol-chat/v1/conversations/abc/messages/1").unwrap();
+        assert_eq!(params.get("*").map(String::as_str), Some("abc/messages/1"));
+        // 通配符至少匹配一段
+        assert!(pattern.matches("/lol-chat/v1/conversations").is_none());
+    }
+
+    #[test]
+    fn middle_wildcard_matches_one_segment() {
+        let pattern = UriPattern::parse("/lol-chat/v1/*/messages");
+        assert!(pattern.matches("/lol-chat/v1/conversations/messages").is_some());
+        assert!(pattern.matches("/lol-chat/v1/conversations/abc/messages").is_none());
+        assert!(pattern.matches("/lol-chat/v1/conversations/messages").unwrap().is_empty());
+    }
+
+    #[test]
+    fn topic_uses_literal_prefix() {
+        assert_eq!(
+            UriPattern::parse("/lol-champ-select/v1/summoners/{cellId}").topic(),
+            "OnJsonApiEvent_lol-champ-select_v1_summoners"
+        );
+        assert_eq!(UriPattern::parse("/lol-gameflow/v1/gameflow-phase").topic(), "OnJsonApiEvent_lol-gameflow_v1_gameflow-phase");
+        // 以参数或通配符开头时订阅所有事件
+        assert_eq!(UriPattern::parse("/{plugin}/v1/session").topic(), "OnJsonApiEvent");
+        assert_eq!(UriPattern::parse("/*").topic(), "OnJsonApiEvent");
+    }
+
+    #[test]
+    fn route_filters_event_types() {
+        let mut router = EventRouter::default();
+        router.add("/lol-lobby/v2/lobby", &[LcuEventType::Delete], "deleted");
+        router.add("/lol-lobby/v2/{resource}", &[], "any");
+
+        let update = lcu_data("/lol-lobby/v2/lobby", LcuEventType::Update);
+        let handlers: Vec<&str> = router.route(&update).into_iter().map(|(handler, _)| *handler).collect();
+        assert_eq!(handlers, vec!["any"]);
+
+        let delete = lcu_data("/lol-lobby/v2/lobby", LcuEventType::Delete);
+        let routes = router.route(&delete);
+        let handlers: Vec<&str> = routes.iter().map(|(handler, _)| **handler).collect();
+        assert_eq!(handlers, vec!["deleted", "any"]);
+        assert_eq!(routes[1].1.get("resource").map(String::as_str), Some("lobby"));
+    }
+
+    #[test]
+    fn remove_where_returns_removed_routes() {
+        let mut router = EventRouter::default();
+        router.add("/a", &[], 1);
+        router.add("/b", &[], 2);
+        let removed = router.remove_where(|handler| *handler == 1);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].pattern.as_str(), "/a");
+        assert_eq!(router.topics(), vec!["OnJsonApiEvent_b".to_string()]);
+    }
+}
@@ -0,0 +1,64 @@
+use reqwest::{Certificate, ClientBuilder};
+use std::error::Error;
+
+/// 关闭证书校验的环境变量，值为 1 或 true 时生效
+pub const INSECURE_TLS_ENV: &str = "PORO_INSECURE_TLS";
+
+/// 关闭证书校验的命令行参数
+pub const INSECURE_TLS_ARG: &str = "--insecure";
+
+/// Riot Games 的自签名根证书，LCU 的证书由它签发
+const RIOT_ROOT_CERT: &[u8] = include_bytes!("riotgames.pem");
+
+/// 连接 LCU 时的证书校验方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TlsMode {
+    /// 只信任 Riot Games 根证书签发的证书
+    #[default]
+    Pinned,
+    /// 接受任何证书，存在 token 被本机其他进程截获的风险
+    Insecure,
+}
+
+impl TlsMode {
+    /// 从命令行参数和环境变量中读取，未指定时校验证书
+    pub fn from_args_and_env() -> Self {
+        let insecure_arg = std::env::args().skip(1).any(|arg| arg == INSECURE_TLS_ARG);
+        let insecure_env = std::env::var(INSECURE_TLS_ENV)
+            .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"));
+        if insecure_arg || insecure_env {
+            TlsMode::Insecure
+        } else {
+            TlsMode::Pinned
+        }
+    }
+
+    /// 配置HTTP客户端的证书校验
+    pub fn configure_http(&self, builder: ClientBuilder) -> Result<ClientBuilder, Box<dyn Error + Send + Sync>> {
+        Ok(match self {
+            // LCU 的证书签发给 127.0.0.1，通过其他地址连接时主机名无法匹配，只校验证书链
+            TlsMode::Pinned => builder
+                .tls_built_in_root_certs(false)
+                .add_root_certificate(Certificate::from_pem(RIOT_ROOT_CERT)?)
+                .danger_accept_invalid_hostnames(true),
+            TlsMode::Insecure => builder.danger_accept_invalid_certs(true),
+        })
+    }
+
+    /// 创建 websocket 使用的 TLS 连接器
+    pub fn websocket_connector(&self) -> Result<native_tls::TlsConnector, Box<dyn Error + Send + Sync>> {
+        let mut builder = native_tls::TlsConnector::builder();
+        builder.danger_accept_invalid_hostnames(true);
+        match self {
+            TlsMode::Pinned => {
+                builder
+                    .disable_built_in_roots(true)
+                    .add_root_certificate(native_tls::Certificate::from_pem(RIOT_ROOT_CERT)?);
+            }
+            TlsMode::Insecure => {
+                builder.danger_accept_invalid_certs(true);
+            }
+        }
+        Ok(builder.build()?)
+    }
+}
@@ -1,62 +1,172 @@
+use crate::lcu::lockfile::{find_lockfile, read_lockfile};
+use crate::lcu::process_inspector::{configured_inspectors, ProcessInspector};
 use base64::engine::general_purpose;
 use base64::Engine;
 use chrono::{DateTime, Local};
-use encoding::all::GBK;
-use encoding::{DecoderTrap, Encoding};
 use std::error::Error;
-use std::io::{BufReader, Read};
-use std::process::{Command, Stdio};
+use std::path::PathBuf;
 
-#[derive(Debug)]
-pub(super) struct LolClientConnectInfo {
+/// 客户端连接信息，port 和 token 之外的字段取决于获取方式，lockfile 中只有 pid
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LolClientConnectInfo {
     pub port: i32,
     pub token: String,
+    /// 客户端所在主机，手动指定时使用，默认为本机
+    pub host: Option<String>,
+    /// 客户端进程 id
+    pub pid: Option<u32>,
+    /// 客户端安装目录
+    pub install_directory: Option<PathBuf>,
+    /// 服务器区域，例如 TENCENT、NA
+    pub region: Option<String>,
+    /// 客户端语言，例如 zh_CN
+    pub locale: Option<String>,
+    /// Riot Client 的端口
+    pub riotclient_port: Option<i32>,
+    /// Riot Client 的认证 token
+    pub riotclient_token: Option<String>,
 }
 
+impl LolClientConnectInfo {
+    /// 客户端所在主机，未指定时为 127.0.0.1
+    pub fn host(&self) -> &str {
+        self.host.as_deref().unwrap_or("127.0.0.1")
+    }
+}
+
+/// 获取到的连接信息及其来源
+#[derive(Debug, Clone)]
+pub struct DiscoveredConnectInfo {
+    pub info: LolClientConnectInfo,
+    /// 成功获取连接信息的方式，例如 lockfile、wmic、proc
+    pub source: &'static str,
+}
+
+/// 获取客户端连接信息，优先读取安装目录下的 lockfile，
+/// 读取失败时再按配置的顺序从进程启动命令中解析
 pub(super) fn get_lol_client_connect_info() -> Result<LolClientConnectInfo, Box<dyn Error>> {
-    // 查询lol的启动进程
-    let output = Command::new("wmic")
-        .args(&[
-            "PROCESS",
-            "WHERE",
-            "name='LeagueClientUx.exe'",
-            "GET",
-            "commandline",
-        ])
-        .stdout(Stdio::piped())
-        .stdin(Stdio::null()) // 屏蔽 stdin，防止其他输入干扰
-        .stderr(Stdio::null()) // 屏蔽 stderr 输出
-        .spawn()?;
-    if let Some(stdout) = output.stdout {
-        let mut reader = BufReader::new(stdout);
-        let mut buffer = Vec::new();
-        reader.read_to_end(&mut buffer)?;
-        // windows 命令需要GBK编码
-        if let Ok(line) = GBK.decode(&buffer, DecoderTrap::Strict) {
-            //通过进程名查询出进程的启动命令,解析出需要的客户端token和端口
-            let token_pattern = regex::Regex::new(r"--remoting-auth-token=([\w-]+)")?;
-            let mut token = String::from("");
-            if let Some(captures) = token_pattern.captures(&line) {
-                if let Some(t) = captures.get(1) {
-                    token = t.as_str().to_string();
-                }
+    if let Some(path) = find_lockfile() {
+        if let Ok(info) = read_lockfile(&path) {
+            return Ok(info);
+        }
+    }
+    let discovered = discover_connect_info(&configured_inspectors())?;
+    println!("{} 通过 {} 获取到客户端连接信息", get_now_str(), discovered.source);
+    Ok(discovered.info)
+}
+
+/// 获取所有正在运行的客户端的连接信息，用于同时管理多个客户端（例如运行在不同 Wine 前缀中的客户端）
+///
+/// lockfile 只能描述一个客户端，因此优先从进程启动命令中查找，都失败时再读取 lockfile
+pub fn get_all_lol_client_connect_info() -> Result<Vec<LolClientConnectInfo>, Box<dyn Error>> {
+    match discover_all_connect_info(&configured_inspectors()) {
+        Ok(discovered) => Ok(discovered.into_iter().map(|d| d.info).collect()),
+        Err(e) => match find_lockfile().map(|path| read_lockfile(&path)) {
+            Some(Ok(info)) => Ok(vec![info]),
+            _ => Err(e),
+        },
+    }
+}
+
+/// 依次尝试各个查询方式，返回第一个查询到客户端的方式解析出的所有连接信息，按端口去重
+pub fn discover_all_connect_info(inspectors: &[Box<dyn ProcessInspector>]) -> Result<Vec<DiscoveredConnectInfo>, Box<dyn Error>> {
+    let mut errors = Vec::new();
+    for inspector in inspectors {
+        let lines = match inspector.command_lines() {
+            Ok(lines) => lines,
+            Err(e) => {
+                errors.push(format!("{}: {}", inspector.name(), e));
+                continue;
             }
-            let port_pattern = regex::Regex::new(r"--app-port=(\d+)")?;
-            let mut port = 0;
-            if let Some(captures) = port_pattern.captures(&line) {
-                if let Some(t) = captures.get(1) {
-                    port = t.as_str().parse::<i32>()?;
-                }
+        };
+        let mut discovered: Vec<DiscoveredConnectInfo> = Vec::new();
+        for info in lines.iter().filter_map(|line| parse_connect_info(line).ok()) {
+            if discovered.iter().all(|d| d.info.port != info.port) {
+                discovered.push(DiscoveredConnectInfo { info, source: inspector.name() });
             }
+        }
+        if !discovered.is_empty() {
+            return Ok(discovered);
+        }
+        errors.push(format!("{}: client process not found", inspector.name()));
+    }
+    Err(From::from(format!("Couldn't get lol client connect ({})", errors.join("; "))))
+}
 
-            if port == 0 || token.is_empty() {
-                return Err(From::from("Couldn't get lol client connect"));
+/// 依次尝试各个查询方式，返回第一个成功解析出的连接信息
+pub fn discover_connect_info(inspectors: &[Box<dyn ProcessInspector>]) -> Result<DiscoveredConnectInfo, Box<dyn Error>> {
+    let mut errors = Vec::new();
+    for inspector in inspectors {
+        let lines = match inspector.command_lines() {
+            Ok(lines) => lines,
+            Err(e) => {
+                errors.push(format!("{}: {}", inspector.name(), e));
+                continue;
             }
+        };
+        match lines.iter().find_map(|line| parse_connect_info(line).ok()) {
+            Some(info) => return Ok(DiscoveredConnectInfo { info, source: inspector.name() }),
+            None => errors.push(format!("{}: client process not found", inspector.name())),
+        }
+    }
+    Err(From::from(format!("Couldn't get lol client connect ({})", errors.join("; "))))
+}
 
-            return Ok(LolClientConnectInfo { port, token });
+/// 通过进程的启动命令,解析出需要的客户端token和端口以及其他启动参数
+pub(super) fn parse_connect_info(line: &str) -> Result<LolClientConnectInfo, Box<dyn Error>> {
+    let mut info = LolClientConnectInfo::default();
+    for arg in tokenize_command_line(line) {
+        let Some((key, value)) = arg.strip_prefix("--").and_then(|arg| arg.split_once('=')) else {
+            continue;
+        };
+        match key {
+            "app-port" => info.port = value.parse::<i32>()?,
+            "remoting-auth-token" => info.token = value.to_string(),
+            "app-pid" => info.pid = value.parse::<u32>().ok(),
+            "install-directory" => info.install_directory = Some(PathBuf::from(value)),
+            "region" => info.region = Some(value.to_string()),
+            "locale" => info.locale = Some(value.to_string()),
+            "riotclient-app-port" => info.riotclient_port = value.parse::<i32>().ok(),
+            "riotclient-auth-token" => info.riotclient_token = Some(value.to_string()),
+            _ => {}
         }
     }
-    Err(From::from("Couldn't get lol client connect"))
+
+    if info.port == 0 || info.token.is_empty() {
+        return Err(From::from("Couldn't get lol client connect"));
+    }
+
+    Ok(info)
+}
+
+/// 按空白拆分启动命令，双引号内的空白不拆分，例如 `"--install-directory=C:/Riot Games/League of Legends"`
+pub(super) fn tokenize_command_line(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+    args
 }
 
 pub(super) fn gen_lcu_auth(username: &str, password: &str) -> String {
@@ -65,8 +175,139 @@ pub(super) fn gen_lcu_auth(username: &str, password: &str) -> String {
     format!("Basic {}", encoded)
 }
 
+/// 日志中的客户端标签前缀，例如 `[召唤师#TAG] `，没有标签时为空
+pub fn label_prefix(label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("[{}] ", label),
+        None => String::new(),
+    }
+}
+
 pub fn get_now_str() -> String {
     let now: DateTime<Local> = Local::now();
-    let time_str = now.time().format("%H:%M:%S").to_string();
-    return time_str;
-}
\ No newline at end of file
+    now.time().format("%H:%M:%S").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcu::process_inspector::{join_args, StaticInspector};
+
+    /// 总是失败的查询方式
+    struct FailingInspector;
+
+    impl ProcessInspector for FailingInspector {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        fn command_lines(&self) -> Result<Vec<String>, Box<dyn Error>> {
+            Err(From::from("not available"))
+        }
+    }
+
+    fn static_inspector(lines: &[&str]) -> Box<dyn ProcessInspector> {
+        Box::new(StaticInspector { lines: lines.iter().map(|line| line.to_string()).collect() })
+    }
+
+    #[test]
+    fn discover_falls_back_past_failing_inspector() {
+        let inspectors = vec![
+            Box::new(FailingInspector) as Box<dyn ProcessInspector>,
+            static_inspector(&["LeagueClientUx.exe --app-port=1000 --remoting-auth-token=a"]),
+        ];
+        let discovered = discover_connect_info(&inspectors).unwrap();
+        assert_eq!(discovered.source, "static");
+        assert_eq!(discovered.info.port, 1000);
+        assert_eq!(discovered.info.token, "a");
+    }
+
+    #[test]
+    fn discover_uses_first_inspector_that_finds_client() {
+        let inspectors = vec![
+            static_inspector(&["LeagueClientUx.exe --app-port=1000 --remoting-auth-token=a"]),
+            Box::new(FailingInspector) as Box<dyn ProcessInspector>,
+        ];
+        let discovered = discover_connect_info(&inspectors).unwrap();
+        assert_eq!(discovered.source, "static");
+        assert_eq!(discovered.info.port, 1000);
+    }
+
+    #[test]
+    fn discover_skips_inspector_without_client() {
+        let inspectors = vec![
+            static_inspector(&["LeagueClientUx.exe --app-port=0"]),
+            static_inspector(&["LeagueClientUx.exe --app-port=2000 --remoting-auth-token=b"]),
+        ];
+        assert_eq!(discover_connect_info(&inspectors).unwrap().info.port, 2000);
+    }
+
+    #[test]
+    fn discover_reports_every_failure() {
+        let inspectors = vec![Box::new(FailingInspector) as Box<dyn ProcessInspector>, static_inspector(&[])];
+        let error = discover_connect_info(&inspectors).unwrap_err().to_string();
+        assert!(error.contains("failing: not available"), "{}", error);
+        assert!(error.contains("static: client process not found"), "{}", error);
+    }
+
+    #[test]
+    fn discover_all_deduplicates_by_port() {
+        let inspectors = vec![
+            Box::new(FailingInspector) as Box<dyn ProcessInspector>,
+            static_inspector(&[
+                "LeagueClientUx.exe --app-port=1000 --remoting-auth-token=a",
+                "LeagueClientUx.exe --app-port=1000 --remoting-auth-token=a",
+                "LeagueClientUx.exe --app-port=2000 --remoting-auth-token=b",
+            ]),
+        ];
+        let discovered = discover_all_connect_info(&inspectors).unwrap();
+        let ports: Vec<i32> = discovered.iter().map(|d| d.info.port).collect();
+        assert_eq!(ports, vec![1000, 2000]);
+        assert!(discovered.iter().all(|d| d.source == "static"));
+    }
+
+    #[test]
+    fn tokenize_keeps_quoted_spaces() {
+        let args = tokenize_command_line(r#""C:\Riot Games\LeagueClientUx.exe" "--install-directory=C:/Riot Games/League of Legends"  --app-port=1"#);
+        assert_eq!(
+            args,
+            vec![r"C:\Riot Games\LeagueClientUx.exe", "--install-directory=C:/Riot Games/League of Legends", "--app-port=1"]
+        );
+    }
+
+    #[test]
+    fn tokenize_keeps_empty_quoted_argument() {
+        assert_eq!(tokenize_command_line(r#"a "" b"#), vec!["a", "", "b"]);
+    }
+
+    #[test]
+    fn tokenize_round_trips_join_args() {
+        let args: Vec<String> = vec![
+            r"C:\Riot Games\League of Legends\LeagueClientUx.exe".to_string(),
+            "--app-port=52437".to_string(),
+            "--install-directory=C:/Riot Games/League of Legends".to_string(),
+        ];
+        assert_eq!(tokenize_command_line(&join_args(&args)), args);
+    }
+
+    #[test]
+    fn parse_connect_info_reads_quoted_install_directory() {
+        let info = parse_connect_info(
+            r#""LeagueClientUx.exe" "--install-directory=C:/Riot Games/League of Legends" --app-port=52437 --remoting-auth-token=abc --app-pid=99 --region=EUW --riotclient-app-port=5000"#,
+        )
+        .unwrap();
+        assert_eq!(info.port, 52437);
+        assert_eq!(info.token, "abc");
+        assert_eq!(info.pid, Some(99));
+        assert_eq!(info.install_directory, Some(PathBuf::from("C:/Riot Games/League of Legends")));
+        assert_eq!(info.region.as_deref(), Some("EUW"));
+        assert_eq!(info.riotclient_port, Some(5000));
+    }
+
+    #[test]
+    fn parse_connect_info_requires_port_and_token() {
+        assert!(parse_connect_info("LeagueClientUx.exe --remoting-auth-token=abc").is_err());
+        assert!(parse_connect_info("LeagueClientUx.exe --app-port=52437").is_err());
+        assert!(parse_connect_info("LeagueClientUx.exe --app-port=abc --remoting-auth-token=abc").is_err());
+    }
+}
@@ -1,45 +1,139 @@
 mod lcu;
 
+use crate::lcu::client_registry::{Assignment, ClientRegistry};
+use crate::lcu::connect_source::ConnectSource;
 use crate::lcu::constants::GameState;
-use crate::lcu::lcu_client::LcuClient;
+use crate::lcu::lcu_client::{ConnectionStatus, LcuClient};
 use crate::lcu::lcu_client_util::accept_game;
-use crate::lcu::utils::get_now_str;
+use crate::lcu::lockfile::{find_lockfile, wait_for_new_connect_info, watch_lockfile};
+use crate::lcu::tls::TlsMode;
+use crate::lcu::utils::{get_all_lol_client_connect_info, get_now_str, LolClientConnectInfo};
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration;
 
 
 #[tokio::main]
 async fn main() {
     println!("{} 启动中...", get_now_str());
+    let connect_source = match ConnectSource::from_args_and_env() {
+        Ok(source) => source,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let tls_mode = TlsMode::from_args_and_env();
+    if tls_mode == TlsMode::Insecure {
+        println!("{} 警告：已关闭证书校验，本机其他进程可能截获客户端 token", get_now_str());
+    }
+    match connect_source {
+        ConnectSource::Manual(info) => {
+            println!("{} 使用手动指定的连接 {}:{}", get_now_str(), info.host(), info.port);
+            run_manual_client(ConnectSource::Manual(info), tls_mode).await;
+        }
+        ConnectSource::Discover => run_discovered_clients(tls_mode).await,
+    }
+}
+
+/// 注册各项功能并运行客户端，连接中断后客户端会自行重连，直到多次重连失败
+async fn run_client(client: &LcuClient) {
+    client.add_game_flow_action(GameState::ReadyCheck, accept_game).await;
+    println!("{} 自动接受对局功能准备完成...", get_now_str());
+    // client.add_game_flow_action(GameState::EndOfGame, play_again).await;
+    println!("{} 自动再来一局功能准备完成...", get_now_str());
+
+    client.exec().await;
+    client.get_stop_notify().notified().await;
+    if let Some(reason) = client.close_reason().await {
+        println!("{} 游戏连接已断开: {}", get_now_str(), reason);
+    }
+}
+
+/// 连接手动指定的客户端，客户端放弃重连后重新创建
+async fn run_manual_client(connect_source: ConnectSource, tls_mode: TlsMode) {
     let mut reconnection_count = 0;
-    
     loop {
         println!("{} 正在连接游戏...", get_now_str());
-        let client = LcuClient::new();
+        let client = LcuClient::new(connect_source.clone(), tls_mode);
         println!("{} 启动完成", get_now_str());
-        client.add_game_flow_action(GameState::ReadyCheck, accept_game).await;
-        println!("{} 自动接受对局功能准备完成...", get_now_str());
-        // client.add_game_flow_action(GameState::EndOfGame, play_again).await;
-        println!("{} 自动再来一局功能准备完成...", get_now_str());
-        
-        client.exec().await;
-        client.get_stop_notify().notified().await;
-        
-        // 检查是否是游戏重启导致的连接中断
-        println!("{} 检测到游戏连接中断，正在尝试重新连接...", get_now_str());
+        run_client(&client).await;
+
+        println!("{} 多次重连游戏失败，稍后重新尝试...", get_now_str());
         reconnection_count += 1;
-        
-        // 创建新的客户端实例来重新连接
-        println!("{} 创建新的连接实例...", get_now_str());
-        
+
         if reconnection_count >= 5 {
             println!("{} 连续重连失败{}次，等待30秒后重试...", get_now_str(), reconnection_count);
-            tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
+            tokio::time::sleep(Duration::from_secs(30)).await;
             reconnection_count = 0;
         } else {
             // 等待一段时间后重试
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            tokio::time::sleep(Duration::from_secs(5)).await;
         }
     }
 }
 
+/// 定期查找所有正在运行的客户端，为每个新出现的客户端创建一个 LcuClient，
+/// 客户端重启后端口和 token 会变化，把新的连接信息交给原来的 LcuClient，由它自行重连
+async fn run_discovered_clients(tls_mode: TlsMode) {
+    let running: Arc<Mutex<ClientRegistry<Arc<LcuClient>>>> = Arc::new(Mutex::new(ClientRegistry::default()));
+    let mut failure_count = 0;
+    // 监听客户端 lockfile，客户端重启后立即重新查找并更新连接信息
+    let mut lockfile_rx = find_lockfile().map(watch_lockfile);
 
+    loop {
+        match get_all_lol_client_connect_info() {
+            Ok(infos) => {
+                failure_count = 0;
+                apply_discovered(&running, infos, tls_mode).await;
+            }
+            Err(_) => failure_count += 1,
+        }
 
+        let wait = if failure_count >= 5 {
+            println!("{} 连续{}次未找到游戏客户端，等待30秒后重试...", get_now_str(), failure_count);
+            failure_count = 0;
+            Duration::from_secs(30)
+        } else {
+            // 等待一段时间后重新查找
+            Duration::from_secs(5)
+        };
+        match lockfile_rx.as_mut() {
+            Some(rx) => {
+                // 客户端重写 lockfile 后不必等满超时，直接把新的连接信息交给对应的客户端，
+                // 此时客户端界面进程可能还未启动，从进程中查找不到
+                if let Some(info) = wait_for_new_connect_info(rx, wait).await {
+                    println!("{} 检测到客户端已重启，立即更新连接信息", get_now_str());
+                    apply_discovered(&running, vec![info], tls_mode).await;
+                }
+            }
+            None => tokio::time::sleep(wait).await,
+        }
+    }
+}
+
+/// 为新出现的客户端创建 LcuClient，把重启后的连接信息交给原来的 LcuClient
+async fn apply_discovered(running: &Arc<Mutex<ClientRegistry<Arc<LcuClient>>>>, infos: Vec<LolClientConnectInfo>, tls_mode: TlsMode) {
+    let assignments = running
+        .lock()
+        .unwrap()
+        .assign(infos, |client| client.connection_status() == ConnectionStatus::Connected);
+    for assignment in assignments {
+        match assignment {
+            Assignment::Restarted(client, info) => {
+                println!("{} 游戏客户端的端口变为{}，使用新的连接信息重连", get_now_str(), info.port);
+                client.set_connect_source(ConnectSource::Manual(info)).await;
+            }
+            Assignment::New(info) => {
+                println!("{} 正在连接端口为{}的游戏客户端...", get_now_str(), info.port);
+                let client = Arc::new(LcuClient::new(ConnectSource::Manual(info.clone()), tls_mode));
+                running.lock().unwrap().insert(client.clone(), info);
+                let c_running = running.clone();
+                tokio::spawn(async move {
+                    run_client(&client).await;
+                    println!("{} 游戏客户端多次重连失败，不再连接", get_now_str());
+                    c_running.lock().unwrap().remove_where(|c| Arc::ptr_eq(c, &client));
+                });
+            }
+        }
+    }
+}